@@ -1,7 +1,8 @@
 use glob::glob;
+use sha2::{Digest, Sha256};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn main() {
@@ -14,12 +15,11 @@ fn main() {
 
     let is_debug = env::var_os("PROFILE") == Some("debug".into());
 
-    let src: &str = "sass/main.scss";
-    let dst: &str = "main.css";
     let out_dir: PathBuf = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     for entry in glob("sass/**/*.scss").expect("Failed to glob") {
         println!("cargo:rerun-if-changed={}", entry.unwrap().display());
     }
+    println!("cargo:rerun-if-changed=static");
 
     /* Compress css in release mode */
     let options = grass::Options::default().style(if is_debug {
@@ -28,7 +28,93 @@ fn main() {
         grass::OutputStyle::Compressed
     });
 
-    let css = grass::from_path(src, &options).unwrap();
-    let dst = out_dir.join(dst);
-    fs::write(dst, &css).unwrap();
+    let mut assets = Assets::default();
+
+    let css = grass::from_path("sass/main.scss", &options).unwrap();
+    assets.add(&out_dir, "main.css", css.into_bytes());
+
+    /* Every file in sass/themes becomes its own stylesheet, loaded on top of main.css */
+    for entry in glob("sass/themes/*.scss").expect("Failed to glob") {
+        let path = entry.unwrap();
+        let name = path.file_stem().unwrap().to_str().unwrap().to_owned();
+        let css = grass::from_path(&path, &options).unwrap();
+        assets.add(&out_dir, &format!("theme-{name}.css"), css.into_bytes());
+    }
+
+    /* Text files may reference other assets as {{name}}, so they go last */
+    let mut files: Vec<PathBuf> = glob("static/*")
+        .expect("Failed to glob")
+        .map(Result::unwrap)
+        .collect();
+    files.sort_by_key(|path| (is_template(path), path.clone()));
+    for path in files {
+        let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+        let mut data = fs::read(&path).unwrap();
+        if is_template(&path) {
+            let mut text = String::from_utf8(data).unwrap();
+            for (name, href) in &assets.hrefs {
+                text = text.replace(&format!("{{{{{name}}}}}"), href);
+            }
+            data = text.into_bytes();
+        }
+        assets.add(&out_dir, &name, data);
+    }
+
+    assets.source.push_str("];\n");
+    fs::write(out_dir.join("assets.rs"), assets.source).unwrap();
+}
+
+fn is_template(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("webmanifest" | "xml" | "txt")
+    )
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("webmanifest") => "application/manifest+json",
+        Some("xml") => "application/opensearchdescription+xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => panic!("Unknown static asset type: {name}"),
+    }
+}
+
+/* Generates the ASSETS table for src/assets.rs, served as /static/<stem>.<hash>.<ext> */
+struct Assets {
+    source: String,
+    hrefs: Vec<(String, String)>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self {
+            source: String::from("pub const ASSETS: &[Asset] = &[\n"),
+            hrefs: vec![],
+        }
+    }
+}
+
+impl Assets {
+    fn add(&mut self, out_dir: &Path, name: &str, data: Vec<u8>) {
+        let hash: String = Sha256::digest(&data)[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let (stem, ext) = name.rsplit_once('.').unwrap();
+        let href = format!("/static/{stem}.{hash}.{ext}");
+
+        let dst = out_dir.join(name);
+        fs::write(&dst, &data).unwrap();
+        self.source.push_str(&format!(
+            "    Asset {{ name: {name:?}, href: {href:?}, content_type: {:?}, data: include_bytes!({:?}) }},\n",
+            content_type(name),
+            dst.display().to_string()
+        ));
+        self.hrefs.push((name.to_owned(), href));
+    }
 }
@@ -2,16 +2,14 @@ let host = "https://neuters.de";
 
 function redirectReuters(url) {
     const path = url.pathname;
-    if (path.startsWith('/article/')
-        || path.startsWith('/pf/')
+    // Api calls and images are loaded by pages, not visited
+    if (path.startsWith('/pf/')
         || path.startsWith('/arc/')
         || path.startsWith('/resizer/')) {
         return null;
-    } else if (path.endsWith('/')) {
-        return `${host}${url.pathname}`;
-    } else {
-        return `${host}${url.pathname}/`;
     }
+    // The instance normalizes the link and picks the matching page
+    return `${host}/go?url=${encodeURIComponent(url.href)}`;
 }
 
 // I hate chromium I hate chromium I hate chromium I hate chromium I hate chromium I hate chromium
@@ -0,0 +1,81 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::{api::error::ApiError, client::Client};
+
+use super::{fetch_latest, objects, signature::signed_post, Federation};
+
+const LISTING_SIZE: u32 = 20;
+
+/* Polls the listings of followed actors and pushes new stories to their followers */
+pub fn spawn(fed: Arc<Federation>, client: Arc<Client>, interval: Duration) {
+    thread::spawn(move || {
+        let mut seen: HashMap<String, HashSet<String>> = HashMap::new();
+        loop {
+            for handle in fed.followed_handles() {
+                poll(&fed, &client, &handle, &mut seen);
+            }
+            thread::sleep(interval);
+        }
+    });
+}
+
+fn poll(
+    fed: &Federation,
+    client: &Client,
+    handle: &str,
+    seen: &mut HashMap<String, HashSet<String>>,
+) {
+    let articles = match fetch_latest(client, handle, LISTING_SIZE) {
+        Ok(articles) => articles.articles.unwrap_or_default(),
+        Err(ApiError::External(404, _)) => {
+            eprintln!("{handle} no longer exists, dropping its followers");
+            fed.remove_actor(handle);
+            seen.remove(handle);
+            return;
+        }
+        Err(err) => {
+            eprintln!("Failed to poll {handle}: {err:?}");
+            return;
+        }
+    };
+
+    let current: HashSet<String> = articles
+        .iter()
+        .map(|article| article.canonical_url.clone())
+        .collect();
+
+    /* The first poll only records what is already there */
+    if let Some(known) = seen.insert(handle.to_owned(), current) {
+        /* Listings are newest first, deliver oldest first */
+        for article in articles.iter().rev() {
+            if known.contains(&article.canonical_url) {
+                continue;
+            }
+            let activity = objects::create(fed, handle, article);
+            deliver(fed, handle, &activity);
+        }
+    }
+}
+
+pub fn deliver(fed: &Federation, handle: &str, activity: &serde_json::Value) {
+    let body = activity.to_string();
+
+    let mut inboxes: Vec<String> = fed
+        .followers(handle)
+        .into_iter()
+        .map(|follower| follower.inbox)
+        .collect();
+    inboxes.sort();
+    inboxes.dedup();
+
+    for inbox in inboxes {
+        if let Err(err) = signed_post(fed, handle, &inbox, body.as_bytes()) {
+            eprintln!("Failed to deliver to {inbox}: {err}");
+        }
+    }
+}
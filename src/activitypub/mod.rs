@@ -0,0 +1,193 @@
+pub mod delivery;
+pub mod objects;
+pub mod signature;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rsa::{
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+    RsaPrivateKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{
+        common::Articles, error::ApiResult, section::fetch_articles_by_section,
+        topic::fetch_articles_by_topic,
+    },
+    client::Client,
+};
+
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+
+const KEY_FILE: &str = "private.pem";
+const FOLLOWERS_FILE: &str = "followers.json";
+
+/* Sections and authors are exposed as read-only actors sharing one instance key */
+pub struct Federation {
+    pub base_url: String,
+    pub host: String,
+    pub agent: ureq::Agent,
+    /* Decides which remote urls may be fetched or posted to, returns their host */
+    pub host_check: fn(&str) -> Result<&str, String>,
+    key: RsaPrivateKey,
+    public_key_pem: String,
+    followers: Mutex<HashMap<String, Vec<Follower>>>,
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Follower {
+    pub actor: String,
+    pub inbox: String,
+}
+
+impl Federation {
+    pub fn new(base_url: &str, agent: ureq::Agent, data_dir: Option<PathBuf>) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let host = base_url
+            .split_once("://")
+            .map_or(base_url.as_str(), |(_, host)| host)
+            .to_owned();
+
+        let key = match &data_dir {
+            Some(dir) => load_or_create_key(dir),
+            None => {
+                eprintln!("No ActivityPub data directory set, using an ephemeral key");
+                generate_key()
+            }
+        };
+        let public_key_pem = key
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .expect("Could not encode public key!");
+
+        let followers = data_dir
+            .as_ref()
+            .and_then(|dir| fs::read(dir.join(FOLLOWERS_FILE)).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        Self {
+            base_url,
+            host,
+            agent,
+            host_check: signature::remote_host,
+            key,
+            public_key_pem,
+            followers: Mutex::new(followers),
+            data_dir,
+        }
+    }
+
+    pub fn actor_id(&self, handle: &str) -> String {
+        format!("{}/ap/actors/{handle}", self.base_url)
+    }
+
+    pub fn key_id(&self, handle: &str) -> String {
+        format!("{}#main-key", self.actor_id(handle))
+    }
+
+    pub fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    pub fn key(&self) -> &RsaPrivateKey {
+        &self.key
+    }
+
+    pub fn followers(&self, handle: &str) -> Vec<Follower> {
+        let followers = self.followers.lock().unwrap();
+        followers.get(handle).cloned().unwrap_or_default()
+    }
+
+    /* Handles of all actors that have at least one follower */
+    pub fn followed_handles(&self) -> Vec<String> {
+        let followers = self.followers.lock().unwrap();
+        followers
+            .iter()
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(handle, _)| handle.clone())
+            .collect()
+    }
+
+    pub fn add_follower(&self, handle: &str, follower: Follower) {
+        let mut followers = self.followers.lock().unwrap();
+        let list = followers.entry(handle.to_owned()).or_default();
+        list.retain(|f| f.actor != follower.actor);
+        list.push(follower);
+        self.store_followers(&followers);
+    }
+
+    pub fn remove_follower(&self, handle: &str, actor: &str) {
+        let mut followers = self.followers.lock().unwrap();
+        if let Some(list) = followers.get_mut(handle) {
+            list.retain(|f| f.actor != actor);
+        }
+        self.store_followers(&followers);
+    }
+
+    /* Drops every follower of an actor that no longer exists */
+    pub fn remove_actor(&self, handle: &str) {
+        let mut followers = self.followers.lock().unwrap();
+        if followers.remove(handle).is_some() {
+            self.store_followers(&followers);
+        }
+    }
+
+    fn store_followers(&self, followers: &HashMap<String, Vec<Follower>>) {
+        let Some(dir) = &self.data_dir else {
+            return;
+        };
+        let result = serde_json::to_vec(followers)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(dir.join(FOLLOWERS_FILE), data).map_err(|e| e.to_string()));
+        if let Err(err) = result {
+            eprintln!("Failed to store followers: {err}");
+        }
+    }
+}
+
+/* "world.europe" -> "/world/europe/", "authors.jane-doe" -> "/authors/jane-doe/" */
+pub fn path_for_handle(handle: &str) -> String {
+    format!("/{}/", handle.replace('.', "/"))
+}
+
+pub fn is_author_handle(handle: &str) -> bool {
+    handle.starts_with("authors.")
+}
+
+fn generate_key() -> RsaPrivateKey {
+    RsaPrivateKey::new(&mut rand::rngs::OsRng, 2048).expect("Could not generate key!")
+}
+
+fn load_or_create_key(dir: &Path) -> RsaPrivateKey {
+    let path = dir.join(KEY_FILE);
+    if let Ok(pem) = fs::read_to_string(&path) {
+        return RsaPrivateKey::from_pkcs8_pem(&pem).expect("Could not parse private key!");
+    }
+
+    println!("Generating ActivityPub key at {}", path.display());
+    let key = generate_key();
+    let pem = key
+        .to_pkcs8_pem(LineEnding::LF)
+        .expect("Could not encode private key!");
+    fs::create_dir_all(dir).expect("Could not create ActivityPub data directory!");
+    fs::write(&path, pem.as_bytes()).expect("Could not store private key!");
+    key
+}
+
+/* Latest stories of the section or author behind an actor */
+pub fn fetch_latest(client: &Client, handle: &str, size: u32) -> ApiResult<Articles> {
+    let path = path_for_handle(handle);
+    if is_author_handle(handle) {
+        fetch_articles_by_topic(client, &path, 0, size)
+    } else {
+        fetch_articles_by_section(client, &path, 0, size)
+    }
+}
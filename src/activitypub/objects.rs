@@ -0,0 +1,105 @@
+use maud::html;
+use serde_json::{json, Value};
+
+use crate::api::common::Article;
+
+use super::Federation;
+
+const CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+const SECURITY_CONTEXT: &str = "https://w3id.org/security/v1";
+
+pub fn actor(fed: &Federation, handle: &str, name: &str, path: &str) -> Value {
+    let id = fed.actor_id(handle);
+    json!({
+        "@context": [CONTEXT, SECURITY_CONTEXT],
+        "id": id,
+        "type": "Service",
+        "preferredUsername": handle,
+        "name": name,
+        "summary": format!("Latest Reuters stories from {name}"),
+        "url": format!("{}{path}", fed.base_url),
+        "inbox": format!("{id}/inbox"),
+        "outbox": format!("{id}/outbox"),
+        "followers": format!("{id}/followers"),
+        "manuallyApprovesFollowers": false,
+        "discoverable": true,
+        "publicKey": {
+            "id": fed.key_id(handle),
+            "owner": id,
+            "publicKeyPem": fed.public_key_pem(),
+        },
+    })
+}
+
+pub fn note(fed: &Federation, handle: &str, article: &Article) -> Value {
+    let actor = fed.actor_id(handle);
+    let url = format!("{}{}", fed.base_url, article.canonical_url);
+    let content = html! {
+        p { a href=(url) { (article.title) } }
+        @if !article.description.is_empty() {
+            p { (article.description) }
+        }
+    };
+    json!({
+        "id": format!("{actor}/notes{}", article.canonical_url),
+        "type": "Note",
+        "attributedTo": actor,
+        "name": article.title,
+        "summary": null,
+        "content": content.into_string(),
+        "url": url,
+        "published": article.published_time,
+        "to": [PUBLIC],
+        "cc": [format!("{actor}/followers")],
+    })
+}
+
+pub fn create(fed: &Federation, handle: &str, article: &Article) -> Value {
+    let actor = fed.actor_id(handle);
+    let note = note(fed, handle, article);
+    json!({
+        "@context": CONTEXT,
+        "id": format!("{}/activity", note["id"].as_str().unwrap_or_default()),
+        "type": "Create",
+        "actor": actor,
+        "published": article.published_time,
+        "to": [PUBLIC],
+        "cc": [format!("{actor}/followers")],
+        "object": note,
+    })
+}
+
+pub fn accept(fed: &Federation, handle: &str, follow: &Value) -> Value {
+    let actor = fed.actor_id(handle);
+    json!({
+        "@context": CONTEXT,
+        "id": format!("{actor}#accepts/{}", chrono::Utc::now().timestamp_millis()),
+        "type": "Accept",
+        "actor": actor,
+        "object": follow,
+    })
+}
+
+pub fn outbox(fed: &Federation, handle: &str, articles: &[Article]) -> Value {
+    let items: Vec<Value> = articles
+        .iter()
+        .map(|article| create(fed, handle, article))
+        .collect();
+    json!({
+        "@context": CONTEXT,
+        "id": format!("{}/outbox", fed.actor_id(handle)),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    })
+}
+
+pub fn followers(fed: &Federation, handle: &str) -> Value {
+    json!({
+        "@context": CONTEXT,
+        "id": format!("{}/followers", fed.actor_id(handle)),
+        "type": "OrderedCollection",
+        "totalItems": fed.followers(handle).len(),
+    })
+}
@@ -0,0 +1,494 @@
+use std::net::IpAddr;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use rsa::{
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::DecodePublicKey,
+    signature::{SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{Digest, Sha256};
+
+use super::{Federation, ACTIVITY_JSON};
+
+pub fn digest(body: &[u8]) -> String {
+    format!("SHA-256={}", BASE64.encode(Sha256::digest(body)))
+}
+
+pub fn http_date() -> String {
+    Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.split_once("://")?.1;
+    match rest.find('/') {
+        Some(index) => Some((&rest[..index], &rest[index..])),
+        None => Some((rest, "/")),
+    }
+}
+
+fn sign(key: &RsaPrivateKey, key_id: &str, headers: &[(&str, &str)]) -> String {
+    let signing_string = headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let names = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let signature = SigningKey::<Sha256>::new(key.clone()).sign(signing_string.as_bytes());
+
+    format!(
+        r#"keyId="{key_id}",algorithm="rsa-sha256",headers="{names}",signature="{}""#,
+        BASE64.encode(signature.to_bytes())
+    )
+}
+
+/* Signed POST as required by Mastodon and most other servers */
+pub fn signed_post(fed: &Federation, handle: &str, url: &str, body: &[u8]) -> Result<(), String> {
+    /* Followers stored before inboxes were checked still go through it here */
+    (fed.host_check)(url)?;
+    let (host, path) = split_url(url).ok_or_else(|| format!("Invalid inbox url: {url}"))?;
+    let date = http_date();
+    let digest = digest(body);
+    let target = format!("post {path}");
+
+    let signature = sign(
+        fed.key(),
+        &fed.key_id(handle),
+        &[
+            ("(request-target)", &target),
+            ("host", host),
+            ("date", &date),
+            ("digest", &digest),
+        ],
+    );
+
+    fed.agent
+        .post(url)
+        .set("Content-Type", ACTIVITY_JSON)
+        .set("Date", &date)
+        .set("Digest", &digest)
+        .set("Signature", &signature)
+        .send_bytes(body)
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+/* Signed GET, needed to fetch actors from servers running in secure mode */
+pub fn signed_get(fed: &Federation, handle: &str, url: &str) -> Result<serde_json::Value, String> {
+    let (host, path) = split_url(url).ok_or_else(|| format!("Invalid actor url: {url}"))?;
+    let date = http_date();
+    let target = format!("get {path}");
+
+    let signature = sign(
+        fed.key(),
+        &fed.key_id(handle),
+        &[
+            ("(request-target)", &target),
+            ("host", host),
+            ("date", &date),
+        ],
+    );
+
+    fed.agent
+        .get(url)
+        .set("Accept", ACTIVITY_JSON)
+        .set("Date", &date)
+        .set("Signature", &signature)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())
+}
+
+struct SignatureParams<'a> {
+    key_id: &'a str,
+    headers: Vec<&'a str>,
+    signature: &'a str,
+}
+
+fn parse_signature(header: &str) -> Result<SignatureParams<'_>, String> {
+    let mut key_id = None;
+    let mut headers = None;
+    let mut signature = None;
+    for field in header.split(',') {
+        let Some((key, value)) = field.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches('"');
+        match key {
+            "keyId" => key_id = Some(value),
+            "headers" => headers = Some(value.split(' ').collect()),
+            "signature" => signature = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(SignatureParams {
+        key_id: key_id.ok_or_else(|| "Missing keyId".to_owned())?,
+        headers: headers.ok_or_else(|| "Missing signed headers".to_owned())?,
+        signature: signature.ok_or_else(|| "Missing signature value".to_owned())?,
+    })
+}
+
+/* Every signature has to cover these, POST bodies are bound to it through the digest */
+fn required_headers(method: &str) -> &'static [&'static str] {
+    if method == "POST" {
+        &["(request-target)", "host", "date", "digest"]
+    } else {
+        &["(request-target)", "host", "date"]
+    }
+}
+
+/* Dates further off than this are rejected, so captured requests can't be replayed later */
+const MAX_DATE_SKEW: i64 = 3600;
+
+fn check_date(date: &str, now: DateTime<Utc>) -> Result<(), String> {
+    let date = DateTime::parse_from_rfc2822(date).map_err(|_| format!("Invalid date {date}"))?;
+    if (now - date.with_timezone(&Utc)).num_seconds().abs() > MAX_DATE_SKEW {
+        return Err("Signature date out of range".to_owned());
+    }
+    Ok(())
+}
+
+/* Everything that can be checked without the signer's key, returns the signed string */
+fn signing_string(
+    request: &rouille::Request,
+    body: &[u8],
+    params: &SignatureParams,
+    now: DateTime<Utc>,
+) -> Result<String, String> {
+    if let Some(name) = required_headers(request.method())
+        .iter()
+        .find(|name| !params.headers.contains(name))
+    {
+        return Err(format!("Signature does not cover {name}"));
+    }
+
+    if request.method() == "POST" {
+        let expected = request
+            .header("Digest")
+            .ok_or_else(|| "Missing digest".to_owned())?;
+        if expected != digest(body) {
+            return Err("Digest mismatch".to_owned());
+        }
+    }
+
+    let date = request
+        .header("Date")
+        .ok_or_else(|| "Missing date".to_owned())?;
+    check_date(date, now)?;
+
+    let mut lines = vec![];
+    for name in &params.headers {
+        let value = match *name {
+            "(request-target)" => {
+                format!("{} {}", request.method().to_lowercase(), request.raw_url())
+            }
+            name => request
+                .header(name)
+                .ok_or_else(|| format!("Missing signed header {name}"))?
+                .to_owned(),
+        };
+        lines.push(format!("{name}: {value}"));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn verify_signature(pem: &str, signing_string: &str, signature: &str) -> Result<(), String> {
+    let key = RsaPublicKey::from_public_key_pem(pem).map_err(|err| err.to_string())?;
+    let signature = BASE64.decode(signature).map_err(|err| err.to_string())?;
+    let signature = Signature::try_from(signature.as_slice()).map_err(|err| err.to_string())?;
+    VerifyingKey::<Sha256>::new(key)
+        .verify(signing_string.as_bytes(), &signature)
+        .map_err(|_| "Invalid signature".to_owned())
+}
+
+/* Host of an https url. Actors are only fetched from and activities only posted to named
+ * remote hosts, never to addresses or local names. */
+pub fn remote_host(url: &str) -> Result<&str, String> {
+    let (host, _) = url
+        .strip_prefix("https://")
+        .and_then(|_| split_url(url))
+        .ok_or_else(|| format!("Invalid remote url {url}"))?;
+    let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+    let allowed = name.contains('.')
+        && name.parse::<IpAddr>().is_err()
+        && !name.ends_with(".localhost")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !allowed {
+        return Err(format!("Host {host} not allowed"));
+    }
+    Ok(host)
+}
+
+/* Verifies the Signature header of an incoming request made on behalf of `actor`, the
+ * actor named in the activity. The actor is only fetched once everything else checks out. */
+pub fn verify_request(
+    fed: &Federation,
+    handle: &str,
+    request: &rouille::Request,
+    body: &[u8],
+    actor: &str,
+) -> Result<serde_json::Value, String> {
+    let header = request
+        .header("Signature")
+        .ok_or_else(|| "Missing signature".to_owned())?;
+    let params = parse_signature(header)?;
+
+    let host = (fed.host_check)(actor)?;
+    if split_url(params.key_id).map(|(key_host, _)| key_host) != Some(host) {
+        return Err("Key is not hosted with the actor".to_owned());
+    }
+    let signing_string = signing_string(request, body, &params, Utc::now())?;
+
+    let signer = signed_get(fed, handle, actor)?;
+    if signer["id"].as_str() != Some(actor)
+        || signer["publicKey"]["id"].as_str() != Some(params.key_id)
+    {
+        return Err("Key does not belong to the actor".to_owned());
+    }
+    let pem = signer["publicKey"]["publicKeyPem"]
+        .as_str()
+        .ok_or_else(|| "Actor has no public key".to_owned())?;
+    verify_signature(pem, &signing_string, params.signature)?;
+
+    Ok(signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Mutex, OnceLock},
+        thread,
+    };
+
+    use rsa::pkcs8::{EncodePublicKey, LineEnding};
+
+    use super::{
+        super::{delivery::deliver, Follower},
+        *,
+    };
+
+    const KEY_ID: &str = "https://remote.example/users/alice#main-key";
+    const TARGET: &str = "/ap/actors/world/inbox";
+
+    fn key() -> &'static RsaPrivateKey {
+        static KEY: OnceLock<RsaPrivateKey> = OnceLock::new();
+        KEY.get_or_init(|| RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap())
+    }
+
+    fn pem() -> String {
+        key()
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap()
+    }
+
+    /* A POST signed over `signed`, carrying `body` */
+    fn request(signed: &[u8], body: &[u8], names: &[&str], date: &str) -> rouille::Request {
+        let digest = digest(signed);
+        let target = format!("post {TARGET}");
+        let values: Vec<(&str, &str)> = names
+            .iter()
+            .map(|name| match *name {
+                "(request-target)" => (*name, target.as_str()),
+                "host" => (*name, "neuters.example"),
+                "date" => (*name, date),
+                "digest" => (*name, digest.as_str()),
+                _ => unreachable!(),
+            })
+            .collect();
+        let signature = sign(key(), KEY_ID, &values);
+
+        rouille::Request::fake_http(
+            "POST",
+            TARGET,
+            vec![
+                ("Host".to_owned(), "neuters.example".to_owned()),
+                ("Date".to_owned(), date.to_owned()),
+                ("Digest".to_owned(), digest),
+                ("Signature".to_owned(), signature),
+            ],
+            body.to_vec(),
+        )
+    }
+
+    fn verify(request: &rouille::Request, body: &[u8]) -> Result<(), String> {
+        let params = parse_signature(request.header("Signature").unwrap())?;
+        let signing_string = signing_string(request, body, &params, Utc::now())?;
+        verify_signature(&pem(), &signing_string, params.signature)
+    }
+
+    const ALL: &[&str] = &["(request-target)", "host", "date", "digest"];
+
+    #[test]
+    fn round_trip() {
+        let body = br#"{"type":"Follow"}"#;
+        assert_eq!(
+            verify(&request(body, body, ALL, &http_date()), body),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn tampered_body() {
+        let body = br#"{"type":"Follow"}"#;
+        let tampered = br#"{"type":"Undo"}"#;
+        let request = request(body, tampered, ALL, &http_date());
+        assert_eq!(
+            verify(&request, tampered),
+            Err("Digest mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_digest() {
+        let body = b"{}";
+        let names = &["(request-target)", "host", "date"];
+        assert_eq!(
+            verify(&request(body, body, names, &http_date()), body),
+            Err("Signature does not cover digest".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_headers_list() {
+        assert_eq!(
+            parse_signature(r#"keyId="a",signature="b""#).err(),
+            Some("Missing signed headers".to_owned())
+        );
+    }
+
+    #[test]
+    fn stale_date() {
+        let body = b"{}";
+        let date = (Utc::now() - chrono::Duration::hours(2))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        assert_eq!(
+            verify(&request(body, body, ALL, &date), body),
+            Err("Signature date out of range".to_owned())
+        );
+    }
+
+    #[test]
+    fn remote_hosts() {
+        assert_eq!(
+            remote_host("https://remote.example/users/alice"),
+            Ok("remote.example")
+        );
+        assert!(remote_host("http://remote.example/users/alice").is_err());
+        assert!(remote_host("https://127.0.0.1/users/alice").is_err());
+        assert!(remote_host("https://[::1]/users/alice").is_err());
+        assert!(remote_host("https://localhost:8080/users/alice").is_err());
+        assert!(remote_host("https://user@remote.example/users/alice").is_err());
+    }
+
+    /* Stands in for remote_host, letting deliveries reach a local inbox */
+    fn loopback_host(url: &str) -> Result<&str, String> {
+        match split_url(url) {
+            Some((host, _)) if url.starts_with("http://127.0.0.1:") => Ok(host),
+            _ => Err(format!("Host of {url} not allowed")),
+        }
+    }
+
+    fn federation(host_check: fn(&str) -> Result<&str, String>) -> Federation {
+        Federation {
+            base_url: "https://neuters.example".to_owned(),
+            host: "neuters.example".to_owned(),
+            agent: ureq::AgentBuilder::new().build(),
+            host_check,
+            key: key().clone(),
+            public_key_pem: pem(),
+            followers: Mutex::new(HashMap::new()),
+            data_dir: None,
+        }
+    }
+
+    /* Accepts a single POST and hands it back as a request the verifier understands */
+    fn inbox() -> (String, thread::JoinHandle<(rouille::Request, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/inbox", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line.split(' ').nth(1).unwrap().to_owned();
+
+            let mut headers = vec![];
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(": ") else {
+                    break;
+                };
+                headers.push((name.to_owned(), value.to_owned()));
+            }
+            let length = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+
+            let request = rouille::Request::fake_http("POST", path, headers, body.clone());
+            (request, body)
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn delivers_signed_activities() {
+        let fed = federation(loopback_host);
+        let (url, received) = inbox();
+        fed.add_follower(
+            "world",
+            Follower {
+                actor: "http://127.0.0.1/users/alice".to_owned(),
+                inbox: url,
+            },
+        );
+        let activity = serde_json::json!({"type": "Create"});
+        deliver(&fed, "world", &activity);
+
+        let (request, body) = received.join().unwrap();
+        assert_eq!(body, activity.to_string().into_bytes());
+        assert_eq!(request.header("Digest"), Some(digest(&body).as_str()));
+        assert_eq!(
+            parse_signature(request.header("Signature").unwrap())
+                .unwrap()
+                .key_id,
+            fed.key_id("world")
+        );
+        assert_eq!(verify(&request, &body), Ok(()));
+    }
+
+    #[test]
+    fn posts_only_to_remote_hosts() {
+        let fed = federation(remote_host);
+        for url in [
+            "http://127.0.0.1:1/inbox",
+            "https://localhost/inbox",
+            "https://10.0.0.1/inbox",
+        ] {
+            assert!(signed_post(&fed, "world", url, b"{}").is_err(), "{url}");
+        }
+    }
+}
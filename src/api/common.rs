@@ -1,18 +1,18 @@
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Articles {
     pub pagination: Pagination,
     pub articles: Option<Box<[Article]>>,
     pub topics: Option<Box<[Topic]>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Pagination {
     pub total_size: Option<u32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Article {
     pub title: String,
     pub subtype: Option<String>,
@@ -24,12 +24,27 @@ pub struct Article {
     pub published_time: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 pub struct Image {
+    pub url: Option<String>,
     pub caption: Option<String>,
+    #[serde(alias = "alt")]
+    pub alt_text: Option<String>,
     pub width: Option<u16>,
     pub height: Option<u16>,
-    pub resizer_url: String,
+    pub resizer_url: Option<String>,
+    pub credits: Option<Credits>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct Credits {
+    pub by: Option<Box<[Credit]>>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Credit {
+    pub name: Option<String>,
+    pub byline: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -40,11 +55,12 @@ pub struct ApiResponse<T> {
     pub result: Option<T>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Topic {
     pub name: String,
     pub topic_url: Option<String>,
     pub byline: String,
+    pub thumbnail: Option<Image>,
 }
 
 #[derive(Clone, Deserialize)]
@@ -31,15 +31,14 @@ where
 
     let response = response.into_json::<ApiResponse<T>>()?;
 
-    if !is_success(response.status_code) || response.result.is_none() {
-        Err(ApiError::External(
+    match response.result {
+        Some(result) if is_success(response.status_code) => Ok(result),
+        _ => Err(ApiError::External(
             response.status_code,
             response
                 .message
                 .unwrap_or_else(|| "Unknown error".to_string()),
-        ))
-    } else {
-        Ok(response.result.unwrap())
+        )),
     }
 }
 
@@ -32,6 +32,7 @@ pub struct LegacyArticleStream {
     pub date: LegacyArticleDate,
     pub authors: Box<[LegacyArticleAuthor]>,
     pub body_items: Box<[LegacyArticleBodyItem]>,
+    pub images: Option<Box<[LegacyArticleImage]>>,
 }
 
 #[derive(Deserialize)]
@@ -45,16 +46,27 @@ pub struct LegacyArticleAuthor {
     pub url: String,
 }
 
+#[derive(Deserialize)]
+pub struct LegacyArticleImage {
+    pub url: String,
+    pub caption: Option<String>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+}
+
 #[derive(Deserialize)]
 pub struct LegacyArticleBodyItem {
     pub r#type: String,
     pub content: String,
 }
 
-pub fn fetch_legacy_article(client: &Client, path: &str) -> Result<ureq::Response, ureq::Error> {
+pub fn fetch_legacy_article(
+    client: &Client,
+    path: &str,
+) -> Result<ureq::Response, Box<ureq::Error>> {
     let link = format!("https://www.reuters.com{path}");
 
-    get(client, &link).call()
+    get(client, &link).call().map_err(Box::new)
 }
 
 pub fn parse_legacy_article(request: ureq::Response) -> ApiResult<LegacyArticle> {
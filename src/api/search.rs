@@ -4,15 +4,68 @@ use super::{common::Articles, error::ApiResult, fetch::fetch};
 
 const API_URL: &str = "https://www.reuters.com/pf/api/v3/content/fetch/articles-by-search-v2";
 
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SearchOrder {
+    Relevance,
+    #[default]
+    Newest,
+    Oldest,
+}
+
+impl SearchOrder {
+    pub const ALL: [Self; 3] = [Self::Relevance, Self::Newest, Self::Oldest];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Relevance => "relevance",
+            Self::Newest => "newest",
+            Self::Oldest => "oldest",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|order| order.as_str() == value)
+    }
+
+    fn orderby(self) -> &'static str {
+        match self {
+            Self::Relevance => "_score:desc",
+            Self::Newest => "display_date:desc",
+            Self::Oldest => "display_date:asc",
+        }
+    }
+}
+
+/* Dates are full timestamps, i.e. 2024-01-31T23:59:59.999Z */
+#[derive(Default)]
+pub struct SearchFilters {
+    pub section: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub order: SearchOrder,
+}
+
 pub fn fetch_articles_by_search(
     client: &Client,
     keyword: &str,
+    filters: &SearchFilters,
     offset: u32,
     size: u32,
 ) -> ApiResult<Articles> {
-    let query = format!(
-        r#"{{"keyword":"{keyword}","offset":{offset},"orderby":"display_date:desc","size":{size},"website":"reuters"}}"#
+    let mut query = format!(
+        r#"{{"keyword":"{keyword}","offset":{offset},"orderby":"{}","size":{size},"website":"reuters""#,
+        filters.order.orderby()
     );
+    if let Some(section) = &filters.section {
+        query.push_str(&format!(r#","sections":"{section}""#));
+    }
+    if let Some(start_date) = &filters.start_date {
+        query.push_str(&format!(r#","start_date":"{start_date}""#));
+    }
+    if let Some(end_date) = &filters.end_date {
+        query.push_str(&format!(r#","end_date":"{end_date}""#));
+    }
+    query.push('}');
 
     fetch(client, API_URL, &query)
 }
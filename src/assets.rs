@@ -0,0 +1,166 @@
+use std::{fs, path::Path, sync::OnceLock};
+
+use maud::{html, Markup};
+use rouille::{Request, Response};
+use sha2::{Digest, Sha256};
+
+use crate::{routes::search::search_enabled, settings::Settings};
+
+pub struct Asset {
+    pub name: &'static str,
+    pub href: &'static str,
+    pub content_type: &'static str,
+    pub data: &'static [u8],
+}
+
+/* Generated by build.rs from the compiled sass and the files in static/ */
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/* Urls change with the content, so responses never have to be revalidated */
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+/* Well known locations can't carry a hash and are revalidated daily */
+const UNHASHED_PATHS: &[&str] = &["/favicon.ico", "/robots.txt"];
+const UNHASHED_CACHE: &str = "public, max-age=86400";
+
+/* Without a public url the search description depends on the Host header */
+const HOST_CACHE: &str = "private, no-cache";
+
+static CUSTOM_CSS: OnceLock<Option<CustomCss>> = OnceLock::new();
+
+struct CustomCss {
+    href: String,
+    css: String,
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/* The operator's custom css is loaded last, so it can override every theme */
+pub fn load_custom_css(path: Option<&Path>) -> Result<(), String> {
+    let custom = match path {
+        Some(path) => {
+            let css =
+                fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+            Some(CustomCss {
+                href: format!("/static/custom.{}.css", content_hash(css.as_bytes())),
+                css,
+            })
+        }
+        None => None,
+    };
+
+    CUSTOM_CSS
+        .set(custom)
+        .map_err(|_| "custom css already loaded".to_owned())
+}
+
+fn custom_css() -> Option<&'static CustomCss> {
+    CUSTOM_CSS.get().and_then(Option::as_ref)
+}
+
+fn asset(name: &str) -> Option<&'static Asset> {
+    ASSETS.iter().find(|asset| asset.name == name)
+}
+
+fn href(name: &str) -> Option<&'static str> {
+    asset(name).map(|asset| asset.href)
+}
+
+/* Stylesheets, icons, manifest and search description for every page */
+pub fn head(settings: &Settings) -> Markup {
+    let theme = href(&format!("theme-{}.css", settings.theme.as_str()));
+
+    html! {
+        link rel="stylesheet" href=[href("main.css")];
+        @if let Some(theme) = theme {
+            link rel="stylesheet" href=(theme);
+        }
+        @if let Some(custom) = custom_css() {
+            link rel="stylesheet" href=(custom.href);
+        }
+        link rel="icon" type="image/svg+xml" href=[href("favicon.svg")];
+        link rel="icon" type="image/png" sizes="32x32" href=[href("favicon-32.png")];
+        link rel="apple-touch-icon" href=[href("apple-touch-icon.png")];
+        link rel="manifest" href=[href("manifest.webmanifest")];
+        @if search_enabled() {
+            link rel="search" type="application/opensearchdescription+xml" title="Neuters" href=[href("opensearch.xml")];
+        }
+    }
+}
+
+/* Absolute urls in the search description point back at this instance */
+fn origin(request: &Request, public_url: Option<&str>) -> String {
+    match public_url {
+        Some(url) => url.trim_end_matches('/').to_owned(),
+        None => format!(
+            "{}://{}",
+            if request.is_secure() { "https" } else { "http" },
+            request.header("Host").unwrap_or("localhost")
+        ),
+    }
+}
+
+/* Serves /static/<stem>.<hash>.<ext> and the well known unhashed paths */
+pub fn serve_static(request: &Request, path: &str, public_url: Option<&str>) -> Option<Response> {
+    if let Some(custom) = custom_css().filter(|custom| custom.href == path) {
+        return Some(
+            Response::from_data("text/css; charset=utf-8", custom.css.clone())
+                .with_unique_header("Cache-Control", IMMUTABLE_CACHE),
+        );
+    }
+
+    let (asset, cache) = if UNHASHED_PATHS.contains(&path) {
+        (asset(&path[1..])?, UNHASHED_CACHE)
+    } else {
+        (
+            ASSETS.iter().find(|asset| asset.href == path)?,
+            IMMUTABLE_CACHE,
+        )
+    };
+
+    /* The hash only covers the template, the origin filled in can change */
+    if asset.name == "opensearch.xml" {
+        if !search_enabled() {
+            return None;
+        }
+        let xml =
+            String::from_utf8_lossy(asset.data).replace("{origin}", &origin(request, public_url));
+        let response = Response::from_data(asset.content_type, xml);
+        return Some(match public_url {
+            Some(_) => response.with_unique_header("Cache-Control", UNHASHED_CACHE),
+            None => response
+                .with_unique_header("Cache-Control", HOST_CACHE)
+                .with_unique_header("Vary", "Host"),
+        });
+    }
+
+    Some(
+        Response::from_data(asset.content_type, asset.data)
+            .with_unique_header("Cache-Control", cache),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins() {
+        let request = Request::fake_http(
+            "GET",
+            "/",
+            vec![("Host".to_owned(), "neuters.example".to_owned())],
+            vec![],
+        );
+        assert_eq!(origin(&request, None), "http://neuters.example");
+        assert_eq!(
+            origin(&request, Some("https://public.example/")),
+            "https://public.example"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+mod markdown;
+
+use std::{fs, io, process::Command};
+
+use neuters::{
+    api::{
+        article::fetch_article_by_url,
+        common::{Article, Section},
+        error::{ApiError, ApiResult},
+        section::{fetch_articles_by_section, fetch_site_hierarchy_by_name},
+    },
+    client::{self, Client},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+
+const PAGE_SIZE: u32 = 20;
+const HELP: &str = "↑/↓ select  Enter open  Esc back  n/p page  o open original  e export  q quit";
+
+struct SectionEntry {
+    id: String,
+    name: String,
+    depth: usize,
+}
+
+enum View {
+    Sections {
+        entries: Vec<SectionEntry>,
+        state: ListState,
+    },
+    Listing {
+        id: String,
+        name: String,
+        offset: u32,
+        total: u32,
+        articles: Vec<Article>,
+        state: ListState,
+    },
+    Article {
+        article: Box<Article>,
+        text: String,
+        scroll: u16,
+    },
+}
+
+struct App {
+    client: Client,
+    views: Vec<View>,
+    status: String,
+}
+
+fn main() -> io::Result<()> {
+    let mut pargs = pico_args::Arguments::from_env();
+
+    let agent = ureq::AgentBuilder::new()
+        .tls_config(client::tls_config())
+        .redirects(0)
+        .build();
+    let mut headers = vec![];
+    if let Ok(cookie) = pargs.value_from_str::<_, String>("--cookie") {
+        headers.push(("Cookie".to_string(), cookie));
+    }
+
+    let mut app = App {
+        client: Client::new(agent, headers),
+        views: vec![],
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    terminal.draw(|frame| frame.render_widget("Fetching site hierarchy...", frame.area()))?;
+    match fetch_site_hierarchy_by_name(&app.client) {
+        Ok(root) => {
+            let mut entries = vec![];
+            flatten_sections(&root, 0, &mut entries);
+            app.views.push(View::Sections {
+                entries,
+                state: ListState::default().with_selected(Some(0)),
+            });
+        }
+        Err(err) => {
+            ratatui::restore();
+            eprintln!("Failed to fetch site hierarchy: {err:?}");
+            return Ok(());
+        }
+    }
+
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn flatten_sections(section: &Section, depth: usize, entries: &mut Vec<SectionEntry>) {
+    entries.push(SectionEntry {
+        id: section.id.clone(),
+        name: section.name.clone(),
+        depth,
+    });
+    for child in section.children.as_deref().unwrap_or_default() {
+        flatten_sections(child, depth + 1, entries);
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.views.is_empty() {
+        terminal.draw(|frame| draw(frame, app))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        app.status.clear();
+
+        match key.code {
+            KeyCode::Char('q') => break,
+            /* Never pop the hierarchy, use q to quit */
+            KeyCode::Esc | KeyCode::Backspace if app.views.len() > 1 => {
+                app.views.pop();
+            }
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+            KeyCode::PageUp => app.move_selection(-10),
+            KeyCode::PageDown | KeyCode::Char(' ') => app.move_selection(10),
+            KeyCode::Enter => {
+                terminal.draw(|frame| draw_loading(frame, app))?;
+                app.open_selected();
+            }
+            KeyCode::Char('n') => {
+                terminal.draw(|frame| draw_loading(frame, app))?;
+                app.change_page(true);
+            }
+            KeyCode::Char('p') => {
+                terminal.draw(|frame| draw_loading(frame, app))?;
+                app.change_page(false);
+            }
+            KeyCode::Char('o') => app.open_original(),
+            KeyCode::Char('e') => {
+                terminal.draw(|frame| draw_loading(frame, app))?;
+                app.export();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl App {
+    fn move_selection(&mut self, delta: i32) {
+        match self.views.last_mut() {
+            Some(View::Sections { state, .. }) | Some(View::Listing { state, .. }) => {
+                if delta < 0 {
+                    state.scroll_up_by(delta.unsigned_abs() as u16);
+                } else {
+                    state.scroll_down_by(delta as u16);
+                }
+            }
+            Some(View::Article { scroll, .. }) => {
+                *scroll = scroll.saturating_add_signed(delta as i16);
+            }
+            None => {}
+        }
+    }
+
+    fn open_selected(&mut self) {
+        let view = match self.views.last() {
+            Some(View::Sections { entries, state }) => {
+                let Some(entry) = state.selected().and_then(|i| entries.get(i)) else {
+                    return;
+                };
+                self.load_listing(&entry.id, &entry.name, 0)
+            }
+            Some(View::Listing {
+                articles, state, ..
+            }) => {
+                let Some(article) = state.selected().and_then(|i| articles.get(i)) else {
+                    return;
+                };
+                self.load_article(&article.canonical_url)
+            }
+            _ => return,
+        };
+        match view {
+            Ok(view) => self.views.push(view),
+            Err(err) => self.status = format!("Failed to load: {err:?}"),
+        }
+    }
+
+    fn change_page(&mut self, forward: bool) {
+        let Some(View::Listing {
+            id,
+            name,
+            offset,
+            total,
+            articles,
+            ..
+        }) = self.views.last()
+        else {
+            return;
+        };
+        let offset = if forward {
+            if offset + (articles.len() as u32) >= *total {
+                self.status = "Already on the last page".to_owned();
+                return;
+            }
+            offset + PAGE_SIZE
+        } else {
+            if *offset == 0 {
+                self.status = "Already on the first page".to_owned();
+                return;
+            }
+            offset.saturating_sub(PAGE_SIZE)
+        };
+
+        match self.load_listing(&id.clone(), &name.clone(), offset) {
+            Ok(view) => {
+                self.views.pop();
+                self.views.push(view);
+            }
+            Err(err) => self.status = format!("Failed to load page: {err:?}"),
+        }
+    }
+
+    fn load_listing(&self, id: &str, name: &str, offset: u32) -> ApiResult<View> {
+        let articles = fetch_articles_by_section(&self.client, id, offset, PAGE_SIZE)?;
+        Ok(View::Listing {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            offset,
+            total: articles.pagination.total_size.unwrap_or(0),
+            articles: articles.articles.map(Vec::from).unwrap_or_default(),
+            state: ListState::default().with_selected(Some(0)),
+        })
+    }
+
+    fn load_article(&self, path: &str) -> ApiResult<View> {
+        let article = match fetch_article_by_url(&self.client, path) {
+            /* Moved articles are only followed once */
+            Err(ApiError::Redirect(_, location)) => {
+                let location = location
+                    .strip_prefix("https://www.reuters.com")
+                    .unwrap_or(&location);
+                fetch_article_by_url(&self.client, location)?
+            }
+            result => result?,
+        };
+        Ok(View::Article {
+            text: markdown::article_to_markdown(&article),
+            article: Box::new(article),
+            scroll: 0,
+        })
+    }
+
+    fn selected_article_path(&self) -> Option<&str> {
+        match self.views.last()? {
+            View::Listing {
+                articles, state, ..
+            } => state
+                .selected()
+                .and_then(|i| articles.get(i))
+                .map(|a| a.canonical_url.as_str()),
+            View::Article { article, .. } => Some(&article.canonical_url),
+            View::Sections { entries, state } => state
+                .selected()
+                .and_then(|i| entries.get(i))
+                .map(|e| e.id.as_str()),
+        }
+    }
+
+    fn open_original(&mut self) {
+        let Some(path) = self.selected_article_path() else {
+            return;
+        };
+        let url = format!("https://www.reuters.com{path}");
+
+        #[cfg(target_os = "windows")]
+        let result = Command::new("cmd").args(["/C", "start", "", &url]).spawn();
+        #[cfg(target_os = "macos")]
+        let result = Command::new("open").arg(&url).spawn();
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let result = Command::new("xdg-open").arg(&url).spawn();
+
+        self.status = match result {
+            Ok(_) => format!("Opened {url}"),
+            Err(err) => format!("Failed to open {url}: {err}"),
+        };
+    }
+
+    fn export(&mut self) {
+        let markdown = match self.views.last() {
+            Some(View::Article { text, .. }) => text.clone(),
+            Some(View::Listing { .. }) => {
+                let Some(path) = self.selected_article_path() else {
+                    return;
+                };
+                match self.load_article(path) {
+                    Ok(View::Article { text, .. }) => text,
+                    Ok(_) => return,
+                    Err(err) => {
+                        self.status = format!("Failed to load article: {err:?}");
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.status = "Select an article to export".to_owned();
+                return;
+            }
+        };
+
+        let Some(path) = self.selected_article_path() else {
+            return;
+        };
+        let slug = path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|slug| !slug.is_empty())
+            .unwrap_or("article");
+        let file = format!("{slug}.md");
+
+        self.status = match fs::write(&file, markdown) {
+            Ok(()) => format!("Exported to {file}"),
+            Err(err) => format!("Failed to write {file}: {err}"),
+        };
+    }
+}
+
+fn draw_loading(frame: &mut Frame, app: &App) {
+    draw(frame, app);
+    let [_, _, status] = layout(frame);
+    frame.render_widget(Line::from("Loading...").reversed(), status);
+}
+
+fn layout(frame: &Frame) -> [ratatui::layout::Rect; 3] {
+    Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area())
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [body, help, status] = layout(frame);
+    let highlight = Style::new().reversed();
+
+    match app.views.last() {
+        Some(View::Sections { entries, state }) => {
+            let items = entries
+                .iter()
+                .map(|entry| ListItem::new(format!("{}{}", "  ".repeat(entry.depth), entry.name)));
+            let list = List::new(items)
+                .block(Block::bordered().title("Reuters sections"))
+                .highlight_style(highlight);
+            frame.render_stateful_widget(list, body, &mut state.clone());
+        }
+        Some(View::Listing {
+            name,
+            offset,
+            total,
+            articles,
+            state,
+            ..
+        }) => {
+            let items = articles.iter().map(|article| {
+                let time = article.published_time.get(..10).unwrap_or_default();
+                ListItem::new(vec![
+                    Line::from(article.title.as_str()).bold(),
+                    Line::from(format!("{time}  {}", article.description)).dim(),
+                ])
+            });
+            let title = format!(
+                "{name} ({} to {} of {total})",
+                offset + 1,
+                offset + articles.len() as u32
+            );
+            let list = List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(highlight);
+            frame.render_stateful_widget(list, body, &mut state.clone());
+        }
+        Some(View::Article {
+            article,
+            text,
+            scroll,
+        }) => {
+            let paragraph = Paragraph::new(text.as_str())
+                .block(Block::bordered().title(article.title.as_str()))
+                .wrap(Wrap { trim: false })
+                .scroll((*scroll, 0));
+            frame.render_widget(paragraph, body);
+        }
+        None => {}
+    }
+
+    frame.render_widget(Line::from(HELP).dim(), help);
+    frame.render_widget(Line::from(app.status.as_str()), status);
+}
@@ -0,0 +1,211 @@
+use std::fmt::Write;
+
+use neuters::api::common::{Article, Topic};
+
+/* Converts an article into Markdown, which doubles as the text shown in the reader */
+pub fn article_to_markdown(article: &Article) -> String {
+    let mut out = format!("# {}\n\n", article.title);
+
+    let mut byline = vec![];
+    if let Some(time) = article.published_time.get(..16) {
+        byline.push(time.replace('T', " "));
+    }
+    if let Some(authors) = &article.authors {
+        if !authors.is_empty() {
+            byline.push(format_authors(authors));
+        }
+    }
+    if !byline.is_empty() {
+        let _ = writeln!(out, "*{}*\n", byline.join(" - "));
+    }
+
+    if !article.description.is_empty() {
+        let _ = writeln!(out, "> {}\n", article.description);
+    }
+
+    render_items(
+        &mut out,
+        article.content_elements.as_deref().unwrap_or_default(),
+        "",
+    );
+
+    let _ = writeln!(
+        out,
+        "---\n\nOriginal: https://www.reuters.com{}",
+        article.canonical_url
+    );
+
+    out
+}
+
+fn format_authors(authors: &[Topic]) -> String {
+    let names: Vec<&str> = authors.iter().map(|a| a.byline.as_str()).collect();
+    match names.split_last() {
+        Some((last, [])) => format!("By {last}"),
+        Some((last, rest)) => format!("By {} and {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
+
+fn render_items(out: &mut String, items: &[serde_json::Value], prefix: &str) {
+    for content in items {
+        match content["type"].as_str() {
+            Some("header") => {
+                if let Some(header) = content["content"].as_str() {
+                    let level = content["level"].as_u64().unwrap_or(0).clamp(1, 4) as usize + 1;
+                    let _ = writeln!(out, "{} {}\n", "#".repeat(level), html_to_markdown(header));
+                }
+            }
+            Some("paragraph") => {
+                if let Some(paragraph) = content["content"].as_str() {
+                    let _ = writeln!(out, "{prefix}{}\n", html_to_markdown(paragraph));
+                }
+            }
+            Some("image") | Some("graphic") => {
+                let caption = content["caption"]
+                    .as_str()
+                    .or_else(|| content["description"].as_str())
+                    .unwrap_or("Image");
+                if let Some(url) = content["url"].as_str() {
+                    let _ = writeln!(out, "![{}]({url})\n", html_to_markdown(caption));
+                }
+            }
+            Some("list") => {
+                if let Some(items) = content["items"].as_array() {
+                    let mut list = String::new();
+                    render_items(&mut list, items, "- ");
+                    /* List items are separated by single newlines */
+                    out.push_str(&list.replace("\n\n", "\n"));
+                    out.push('\n');
+                }
+            }
+            Some("table") => {
+                if let Some(rows) = content["rows"].as_array() {
+                    render_table(out, rows);
+                }
+            }
+            Some("social_media") => {
+                let _ = writeln!(out, "*[Embedded social media post]*\n");
+            }
+            _ => {}
+        }
+    }
+}
+
+fn render_table(out: &mut String, rows: &[serde_json::Value]) {
+    for (index, row) in rows.iter().enumerate() {
+        let Some(cells) = row.as_array() else {
+            continue;
+        };
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|cell| html_to_markdown(cell.as_str().unwrap_or_default()).replace('|', "\\|"))
+            .collect();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+        if index == 0 {
+            let _ = writeln!(out, "|{}", " --- |".repeat(cells.len()));
+        }
+    }
+    out.push('\n');
+}
+
+/* Reuters paragraphs contain inline markup, mostly links and emphasis */
+pub fn html_to_markdown(html: &str) -> String {
+    let Ok(dom) = tl::parse(html, tl::ParserOptions::default()) else {
+        return decode_entities(html);
+    };
+    let parser = dom.parser();
+    let mut out = String::new();
+    for handle in dom.children() {
+        write_node(&mut out, handle, parser);
+    }
+    out.trim().to_owned()
+}
+
+fn write_node(out: &mut String, handle: &tl::NodeHandle, parser: &tl::Parser) {
+    let Some(node) = handle.get(parser) else {
+        return;
+    };
+    match node {
+        tl::Node::Raw(text) => out.push_str(&decode_entities(&text.as_utf8_str())),
+        tl::Node::Tag(tag) => {
+            let mut inner = String::new();
+            for child in tag.children().top().iter() {
+                write_node(&mut inner, child, parser);
+            }
+            match tag.name().as_utf8_str().as_ref() {
+                "a" => {
+                    let href = tag
+                        .attributes()
+                        .get("href")
+                        .flatten()
+                        .map(|href| href.as_utf8_str().into_owned());
+                    match href {
+                        Some(href) if href.starts_with('/') => {
+                            let _ = write!(out, "[{inner}](https://www.reuters.com{href})");
+                        }
+                        Some(href) => {
+                            let _ = write!(out, "[{inner}]({href})");
+                        }
+                        None => out.push_str(&inner),
+                    }
+                }
+                "b" | "strong" => {
+                    let _ = write!(out, "**{inner}**");
+                }
+                "i" | "em" => {
+                    let _ = write!(out, "*{inner}*");
+                }
+                "br" => out.push_str("  \n"),
+                _ => out.push_str(&inner),
+            }
+        }
+        tl::Node::Comment(_) => {}
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_markup() {
+        assert_eq!(
+            html_to_markdown(r#"A <b>bold</b> and <i>quiet</i> <a href="/world/">link</a>"#),
+            "A **bold** and *quiet* [link](https://www.reuters.com/world/)"
+        );
+        assert_eq!(
+            html_to_markdown(r#"<a href="https://example.com">out</a>"#),
+            "[out](https://example.com)"
+        );
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(html_to_markdown("Q&amp;A &quot;now&quot;"), "Q&A \"now\"");
+    }
+
+    #[test]
+    fn authors() {
+        let author = |byline: &str| Topic {
+            name: byline.to_owned(),
+            topic_url: None,
+            byline: byline.to_owned(),
+            thumbnail: None,
+        };
+        assert_eq!(format_authors(&[author("A")]), "By A");
+        assert_eq!(
+            format_authors(&[author("A"), author("B"), author("C")]),
+            "By A, B and C"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use ureq::Request;
 
 pub struct Client {
@@ -18,3 +20,20 @@ impl Client {
         request
     }
 }
+
+pub fn tls_config() -> Arc<rustls::ClientConfig> {
+    let certs = rustls_native_certs::load_native_certs().expect("Could not load certs!");
+
+    let mut root_store = rustls::RootCertStore::empty();
+    for cert in certs {
+        root_store
+            .add(&rustls::Certificate(cert.0))
+            .expect("Could not add cert!");
+    }
+    Arc::new(
+        rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    )
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use neuters::{
+    api::{
+        common::{Article, Articles},
+        error::ApiResult,
+        section::fetch_articles_by_section,
+        topic::fetch_articles_by_topic,
+    },
+    client::Client,
+};
+
+/* Listings change every few minutes, there is no need to ask more often */
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+/* Followed listings vary per reader, expired ones are dropped once the cache is full */
+const CACHE_ENTRIES: usize = 1024;
+
+/* Listings fetched at once */
+const FETCH_THREADS: usize = 8;
+
+/* Extra stories fetched per section to make up for duplicates */
+const DEDUPE_SLACK: u32 = 4;
+
+pub struct Block {
+    pub path: String,
+    pub articles: Arc<Articles>,
+}
+
+/* Authors and topics have their own listing endpoint, everything else is a section */
+fn fetch_listing(client: &Client, path: &str, size: u32) -> ApiResult<Articles> {
+    if path.starts_with("/authors/") || path.starts_with("/topic/") {
+        fetch_articles_by_topic(client, path, 0, size)
+    } else {
+        fetch_articles_by_section(client, path, 0, size)
+    }
+}
+
+/* Latest stories of several listings, fetched concurrently and cached per listing */
+pub struct FrontPage {
+    sections: Vec<String>,
+    size: u32,
+    cache: Mutex<HashMap<String, (Instant, Arc<Articles>)>>,
+}
+
+impl FrontPage {
+    pub fn new(sections: Vec<String>, size: u32) -> Self {
+        Self {
+            sections,
+            size: size.clamp(1, 20),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn cached(&self, path: &str) -> Option<Arc<Articles>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(path)
+            .filter(|(fetched, _)| fetched.elapsed() < CACHE_TTL)
+            .map(|(_, articles)| articles.clone())
+    }
+
+    fn fetch(&self, client: &Client, path: &str) -> Option<Arc<Articles>> {
+        if let Some(articles) = self.cached(path) {
+            return Some(articles);
+        }
+        match fetch_listing(client, path, self.size + DEDUPE_SLACK) {
+            Ok(articles) => {
+                let articles = Arc::new(articles);
+                let mut cache = self.cache.lock().unwrap();
+                if cache.len() >= CACHE_ENTRIES {
+                    cache.retain(|_, (fetched, _)| fetched.elapsed() < CACHE_TTL);
+                }
+                if cache.len() < CACHE_ENTRIES || cache.contains_key(path) {
+                    cache.insert(path.to_owned(), (Instant::now(), articles.clone()));
+                }
+                Some(articles)
+            }
+            Err(err) => {
+                eprintln!("Failed to fetch listing {path}: {err:?}");
+                None
+            }
+        }
+    }
+
+    pub fn blocks(&self, client: &Client) -> Vec<Block> {
+        self.listings(client, &self.sections)
+    }
+
+    /* Cached listings plus at most `max_fetches` fetched ones, so a long list of rarely
+     * read listings can't fan out upstream on a single request. Returns how many were
+     * left out for now, they fill in on later requests. */
+    pub fn cached_listings(
+        &self,
+        client: &Client,
+        paths: &[String],
+        max_fetches: usize,
+    ) -> (Vec<Block>, usize) {
+        let mut fetches = 0;
+        let allowed: Vec<String> = paths
+            .iter()
+            .filter(|path| {
+                self.cached(path).is_some() || {
+                    fetches += 1;
+                    fetches <= max_fetches
+                }
+            })
+            .cloned()
+            .collect();
+        let skipped = paths.len() - allowed.len();
+        (self.listings(client, &allowed), skipped)
+    }
+
+    /* Listings that failed to load are left out */
+    pub fn listings(&self, client: &Client, paths: &[String]) -> Vec<Block> {
+        paths
+            .chunks(FETCH_THREADS)
+            .flat_map(|chunk| {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = chunk
+                        .iter()
+                        .map(|path| scope.spawn(move || (path, self.fetch(client, path))))
+                        .collect();
+                    handles
+                        .into_iter()
+                        .filter_map(|handle| match handle.join() {
+                            Ok((path, Some(articles))) => Some(Block {
+                                path: path.clone(),
+                                articles,
+                            }),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect()
+    }
+}
+
+/* Stories are shown in the first section listing them, each block keeps at most `size` */
+pub fn dedupe(blocks: &[Block], size: u32) -> Vec<Vec<&Article>> {
+    let mut seen = HashSet::new();
+    blocks
+        .iter()
+        .map(|block| {
+            block
+                .articles
+                .articles
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .filter(|article| seen.insert(article.canonical_url.as_str()))
+                .take(size as usize)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{article, articles};
+
+    fn block(path: &str, urls: &[&str]) -> Block {
+        let list = urls.iter().map(|url| article(url, "")).collect();
+        Block {
+            path: path.to_owned(),
+            articles: Arc::new(articles(list, None)),
+        }
+    }
+
+    #[test]
+    fn only_cached_listings_without_fetches() {
+        let front_page = FrontPage::new(vec![], 8);
+        front_page.cache.lock().unwrap().insert(
+            "/world/".to_owned(),
+            (Instant::now(), block("/world/", &["/a/"]).articles),
+        );
+        let client = Client::new(ureq::AgentBuilder::new().build(), vec![]);
+        let paths = ["/business/", "/world/", "/markets/"].map(str::to_owned);
+
+        let (blocks, skipped) = front_page.cached_listings(&client, &paths, 0);
+        let paths: Vec<&str> = blocks.iter().map(|block| block.path.as_str()).collect();
+        assert_eq!((paths, skipped), (vec!["/world/"], 2));
+    }
+
+    #[test]
+    fn stories_show_once() {
+        let blocks = [
+            block("/world/", &["/a/", "/b/", "/c/"]),
+            block("/business/", &["/b/", "/d/", "/a/", "/e/", "/f/"]),
+            block("/markets/", &["/d/"]),
+        ];
+        let urls: Vec<Vec<&str>> = dedupe(&blocks, 2)
+            .iter()
+            .map(|articles| {
+                articles
+                    .iter()
+                    .map(|article| article.canonical_url.as_str())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(urls, [vec!["/a/", "/b/"], vec!["/d/", "/e/"], vec![]]);
+    }
+}
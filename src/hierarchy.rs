@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use neuters::{
+    api::{self, common},
+    client::Client,
+};
+
+use crate::{Section, SectionChild};
+
+pub type SectionMap = HashMap<String, Section>;
+
+/* The hierarchy is rooted at "/", its children are the top-level sections */
+pub const ROOT_SECTION: &str = "/";
+
+/* Shipped with the binary, used until the first successful fetch */
+const SNAPSHOT: &str = include_str!("../data/site-hierarchy.json");
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/* Site hierarchy shared by all requests, swapped as a whole on refresh */
+pub struct Hierarchy {
+    sections: RwLock<Arc<SectionMap>>,
+    from_snapshot: bool,
+}
+
+impl Hierarchy {
+    /* Fetches the live hierarchy, falling back to the bundled snapshot */
+    pub fn load(client: &Client) -> Self {
+        println!("Fetching site hierarchy");
+        let (sections, from_snapshot) = match api::section::fetch_site_hierarchy_by_name(client) {
+            Ok(root) => {
+                println!("Fetched site hierarchy");
+                (flatten(root), false)
+            }
+            Err(err) => {
+                eprintln!("Failed to fetch site hierarchy, using bundled snapshot: {err:?}");
+                let root = serde_json::from_str(SNAPSHOT).expect("Bundled hierarchy is valid");
+                (flatten(root), true)
+            }
+        };
+        println!("Sections: {}", sections.len());
+
+        Self {
+            sections: RwLock::new(Arc::new(sections)),
+            from_snapshot,
+        }
+    }
+
+    /* Requests keep their snapshot even if a refresh lands in the meantime */
+    pub fn sections(&self) -> Arc<SectionMap> {
+        self.sections.read().unwrap().clone()
+    }
+
+    fn replace(&self, sections: SectionMap) {
+        let old = self.sections();
+
+        let mut added: Vec<&str> = sections
+            .keys()
+            .filter(|id| !old.contains_key(*id))
+            .map(String::as_str)
+            .collect();
+        let mut removed: Vec<&str> = old
+            .keys()
+            .filter(|id| !sections.contains_key(*id))
+            .map(String::as_str)
+            .collect();
+        added.sort_unstable();
+        removed.sort_unstable();
+        if !added.is_empty() {
+            println!("Sections added: {}", added.join(", "));
+        }
+        if !removed.is_empty() {
+            println!("Sections removed: {}", removed.join(", "));
+        }
+
+        *self.sections.write().unwrap() = Arc::new(sections);
+    }
+}
+
+fn flatten(root: common::Section) -> SectionMap {
+    let mut sections_by_id = SectionMap::new();
+    let mut queue = vec![(root, None)];
+    while let Some((section, parent)) = queue.pop() {
+        let children = section
+            .children
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|s| SectionChild {
+                id: s.id.clone(),
+                name: s.name.clone(),
+            })
+            .collect();
+        for child in section.children.unwrap_or_default() {
+            queue.push((child, Some(section.id.clone())));
+        }
+        let node = Section {
+            id: section.id.clone(),
+            name: section.name.clone(),
+            parent,
+            children,
+        };
+        sections_by_id.insert(section.id, node);
+    }
+    sections_by_id
+}
+
+/* Refreshes on a schedule, failed fetches are retried sooner and keep the current hierarchy */
+pub fn spawn_refresh(hierarchy: Arc<Hierarchy>, client: Arc<Client>, interval: Duration) {
+    thread::spawn(move || {
+        let mut wait = if hierarchy.from_snapshot {
+            RETRY_INTERVAL.min(interval)
+        } else {
+            interval
+        };
+        loop {
+            thread::sleep(wait);
+            wait = match api::section::fetch_site_hierarchy_by_name(&client) {
+                Ok(root) => {
+                    hierarchy.replace(flatten(root));
+                    interval
+                }
+                Err(err) => {
+                    eprintln!("Failed to refresh site hierarchy: {err:?}");
+                    RETRY_INTERVAL.min(interval)
+                }
+            };
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_the_snapshot() {
+        let sections = flatten(serde_json::from_str(SNAPSHOT).unwrap());
+        assert!(sections[ROOT_SECTION].parent.is_none());
+        for section in sections.values() {
+            if section.id != ROOT_SECTION {
+                let parent = &sections[section.parent.as_ref().unwrap()];
+                assert!(parent.children.iter().any(|child| child.id == section.id));
+            }
+            for child in &section.children {
+                assert_eq!(sections[&child.id].parent.as_ref(), Some(&section.id));
+            }
+        }
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock, RwLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use neuters::api::{
+    common::{Article, Topic},
+    search::SearchOrder,
+};
+use serde::{Deserialize, Serialize};
+
+static INDEX: OnceLock<Option<LocalIndex>> = OnceLock::new();
+
+/* Unsaved articles are written out this often */
+const SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/* Words in the title count more than words in the body */
+const TITLE_WEIGHT: f32 = 3.0;
+const DESCRIPTION_WEIGHT: f32 = 2.0;
+const AUTHOR_WEIGHT: f32 = 2.0;
+
+/* BM25 parameters */
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+const TERM_MAX_CHARS: usize = 40;
+const SNIPPET_WORDS: usize = 30;
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub authors: Vec<String>,
+    /* Path of the section the article was published in, i.e. /world/europe/ */
+    pub section: String,
+    pub published_time: String,
+    #[serde(skip)]
+    terms: HashMap<String, f32>,
+    #[serde(skip)]
+    length: f32,
+}
+
+#[derive(Default)]
+struct Inner {
+    documents: HashMap<String, Document>,
+    /* Urls, least recently indexed first */
+    order: VecDeque<String>,
+    postings: HashMap<String, HashSet<String>>,
+    total_length: f32,
+}
+
+pub struct LocalIndex {
+    inner: RwLock<Inner>,
+    capacity: usize,
+    data_file: Option<PathBuf>,
+    dirty: AtomicBool,
+}
+
+/* Lowercased words, the same for documents and queries */
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| (2..=TERM_MAX_CHARS).contains(&word.chars().count()))
+        .map(str::to_lowercase)
+}
+
+/* Article paragraphs are html, the index only keeps their text */
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn body_text(elements: &[serde_json::Value], text: &mut String) {
+    for element in elements {
+        match element["type"].as_str() {
+            Some("paragraph" | "header") => {
+                if let Some(content) = element["content"].as_str() {
+                    text.push_str(&strip_tags(content));
+                    text.push('\n');
+                }
+            }
+            Some("list") => {
+                if let Some(items) = element["items"].as_array() {
+                    body_text(items, text);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/* "/world/europe/some-story-2024-01-01/" -> "/world/europe/" */
+fn section_of(url: &str) -> String {
+    match url.trim_end_matches('/').rsplit_once('/') {
+        Some((section, _)) => format!("{section}/"),
+        None => "/".to_owned(),
+    }
+}
+
+impl Document {
+    fn from_article(article: &Article) -> Self {
+        let mut body = String::new();
+        body_text(
+            article.content_elements.as_deref().unwrap_or_default(),
+            &mut body,
+        );
+
+        let mut document = Self {
+            url: article.canonical_url.clone(),
+            title: article.title.clone(),
+            description: article.description.clone(),
+            body,
+            authors: article
+                .authors
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .map(|author| author.name.clone())
+                .collect(),
+            section: section_of(&article.canonical_url),
+            published_time: article.published_time.clone(),
+            terms: HashMap::new(),
+            length: 0.0,
+        };
+        document.count_terms();
+        document
+    }
+
+    fn count_terms(&mut self) {
+        let mut terms = HashMap::new();
+        let fields = [
+            (self.title.as_str(), TITLE_WEIGHT),
+            (self.description.as_str(), DESCRIPTION_WEIGHT),
+            (self.body.as_str(), 1.0),
+        ]
+        .into_iter()
+        .chain(
+            self.authors
+                .iter()
+                .map(|author| (author.as_str(), AUTHOR_WEIGHT)),
+        );
+        for (text, weight) in fields {
+            for term in tokenize(text) {
+                *terms.entry(term).or_insert(0.0) += weight;
+            }
+        }
+        self.length = terms.values().sum();
+        self.terms = terms;
+    }
+
+    /* Words of the body around the first match, falling back to the description */
+    pub fn snippet(&self, terms: &[String]) -> String {
+        let words: Vec<&str> = self.body.split_whitespace().collect();
+        let first = words
+            .iter()
+            .position(|word| tokenize(word).any(|token| terms.contains(&token)));
+        let Some(first) = first else {
+            return self.description.clone();
+        };
+
+        let start = first.saturating_sub(SNIPPET_WORDS / 3);
+        let end = (start + SNIPPET_WORDS).min(words.len());
+        let mut snippet = words[start..end].join(" ");
+        if start > 0 {
+            snippet.insert_str(0, "… ");
+        }
+        if end < words.len() {
+            snippet.push_str(" …");
+        }
+        snippet
+    }
+
+    /* Shaped like an upstream search result, with the snippet as description */
+    fn to_article(&self, terms: &[String]) -> Article {
+        Article {
+            title: self.title.clone(),
+            subtype: None,
+            canonical_url: self.url.clone(),
+            description: self.snippet(terms),
+            content_elements: None,
+            authors: Some(
+                self.authors
+                    .iter()
+                    .map(|name| Topic {
+                        name: name.clone(),
+                        topic_url: None,
+                        byline: name.clone(),
+                        thumbnail: None,
+                    })
+                    .collect(),
+            ),
+            thumbnail: None,
+            published_time: self.published_time.clone(),
+        }
+    }
+}
+
+impl Inner {
+    /* Articles are recorded on every view, most of them haven't changed since */
+    fn is_unchanged(&self, document: &Document) -> bool {
+        self.documents.get(&document.url).is_some_and(|indexed| {
+            indexed.published_time == document.published_time
+                && indexed.title == document.title
+                && indexed.description == document.description
+                && indexed.body == document.body
+                && indexed.authors == document.authors
+        })
+    }
+
+    fn insert(&mut self, document: Document) {
+        if self.documents.contains_key(&document.url) {
+            self.unindex(&document.url);
+            self.order.retain(|indexed| *indexed != document.url);
+        }
+        for term in document.terms.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(document.url.clone());
+        }
+        self.total_length += document.length;
+        self.order.push_back(document.url.clone());
+        self.documents.insert(document.url.clone(), document);
+    }
+
+    fn trim(&mut self, capacity: usize) {
+        while self.documents.len() > capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.unindex(&oldest);
+        }
+    }
+
+    /* Leaves the url in `order`, callers take it out from where they know it is */
+    fn unindex(&mut self, url: &str) {
+        let Some(document) = self.documents.remove(url) else {
+            return;
+        };
+        for term in document.terms.keys() {
+            if let Some(urls) = self.postings.get_mut(term) {
+                urls.remove(url);
+                if urls.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_length -= document.length;
+    }
+
+    /* BM25 over the weighted term counts, documents need at least one term */
+    fn scores(&self, terms: &[String]) -> HashMap<&str, f32> {
+        let count = self.documents.len() as f32;
+        let average = self.total_length / count.max(1.0);
+        let mut scores = HashMap::new();
+        for term in terms {
+            let Some(urls) = self.postings.get(term) else {
+                continue;
+            };
+            let idf = ((count - urls.len() as f32 + 0.5) / (urls.len() as f32 + 0.5) + 1.0).ln();
+            for url in urls {
+                let document = &self.documents[url];
+                let tf = document.terms[term];
+                let norm = K1 * (1.0 - B + B * document.length / average.max(1.0));
+                *scores.entry(url.as_str()).or_insert(0.0) += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+        scores
+    }
+}
+
+/* A data file that can't be read is moved aside, the index starts over instead */
+fn load(path: &Path, capacity: usize) -> Inner {
+    let mut inner = Inner::default();
+    let documents = fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|data| {
+            serde_json::from_slice::<Vec<Document>>(&data).map_err(|err| err.to_string())
+        });
+    match documents {
+        Ok(documents) => {
+            for mut document in documents {
+                document.count_terms();
+                inner.insert(document);
+            }
+            inner.trim(capacity);
+        }
+        Err(err) => {
+            let aside = path.with_extension("corrupt");
+            eprintln!(
+                "Failed to load local index from {}: {err}, moving it to {} and starting empty",
+                path.display(),
+                aside.display()
+            );
+            if let Err(err) = fs::rename(path, &aside) {
+                eprintln!("Failed to move {}: {err}", path.display());
+            }
+        }
+    }
+    inner
+}
+
+/* The index stays off unless the operator turns it on, articles are only kept in memory
+ * without a data file */
+pub fn init(enabled: bool, data_file: Option<PathBuf>, capacity: usize) {
+    let index = enabled.then(|| {
+        let capacity = capacity.max(1);
+        let inner = match data_file.as_ref().filter(|path| path.exists()) {
+            Some(path) => load(path, capacity),
+            None => Inner::default(),
+        };
+        println!("Local index: {} articles", inner.documents.len());
+
+        LocalIndex {
+            inner: RwLock::new(inner),
+            capacity,
+            data_file,
+            dirty: AtomicBool::new(false),
+        }
+    });
+    if INDEX.set(index).is_err() {
+        panic!("Local index already initialized");
+    }
+
+    if let Some(index) = self::index().filter(|index| index.data_file.is_some()) {
+        thread::spawn(move || loop {
+            thread::sleep(SAVE_INTERVAL);
+            index.save();
+        });
+    }
+}
+
+pub fn index() -> Option<&'static LocalIndex> {
+    INDEX.get().and_then(Option::as_ref)
+}
+
+pub fn enabled() -> bool {
+    index().is_some()
+}
+
+/* Called for every article served, does nothing while the index is off */
+pub fn record(article: &Article) {
+    if let Some(index) = index() {
+        index.insert(Document::from_article(article));
+    }
+}
+
+impl LocalIndex {
+    fn insert(&self, document: Document) {
+        if self.inner.read().unwrap().is_unchanged(&document) {
+            return;
+        }
+        let mut inner = self.inner.write().unwrap();
+        inner.insert(document);
+        inner.trim(self.capacity);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.data_file else {
+            return;
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let data = {
+            let inner = self.inner.read().unwrap();
+            let documents: Vec<&Document> = inner
+                .order
+                .iter()
+                .filter_map(|url| inner.documents.get(url))
+                .collect();
+            serde_json::to_vec(&documents)
+        };
+        /* Written next to the index first, so a crash never leaves half a file */
+        let temporary = path.with_extension("tmp");
+        let result = data
+            .map_err(|err| err.to_string())
+            .and_then(|data| fs::write(&temporary, data).map_err(|err| err.to_string()))
+            .and_then(|_| fs::rename(&temporary, path).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            eprintln!("Failed to store local index: {err}");
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /* Urls of the documents matching any of the terms and the filter, in the given order */
+    pub fn search(
+        &self,
+        terms: &[String],
+        order: SearchOrder,
+        filter: impl Fn(&Document) -> bool,
+    ) -> Vec<String> {
+        let inner = self.inner.read().unwrap();
+        let mut hits: Vec<(&Document, f32)> = inner
+            .scores(terms)
+            .into_iter()
+            .map(|(url, score)| (&inner.documents[url], score))
+            .filter(|(document, _)| filter(document))
+            .collect();
+        match order {
+            SearchOrder::Relevance => hits.sort_by(|(_, a), (_, b)| b.total_cmp(a)),
+            SearchOrder::Newest => {
+                hits.sort_by(|(a, _), (b, _)| b.published_time.cmp(&a.published_time))
+            }
+            SearchOrder::Oldest => {
+                hits.sort_by(|(a, _), (b, _)| a.published_time.cmp(&b.published_time))
+            }
+        }
+        hits.into_iter()
+            .map(|(document, _)| document.url.clone())
+            .collect()
+    }
+
+    /* Only the page shown is turned into articles, documents trimmed since are left out */
+    pub fn articles(&self, urls: &[String], terms: &[String]) -> Vec<Article> {
+        let inner = self.inner.read().unwrap();
+        urls.iter()
+            .filter_map(|url| inner.documents.get(url))
+            .map(|document| document.to_article(terms))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(url: &str, title: &str, body: &str, published_time: &str) -> Document {
+        let mut document = Document {
+            url: url.to_owned(),
+            title: title.to_owned(),
+            description: String::new(),
+            body: body.to_owned(),
+            authors: vec!["Jane Doe".to_owned()],
+            section: section_of(url),
+            published_time: published_time.to_owned(),
+            terms: HashMap::new(),
+            length: 0.0,
+        };
+        document.count_terms();
+        document
+    }
+
+    fn index(capacity: usize) -> LocalIndex {
+        LocalIndex {
+            inner: RwLock::new(Inner::default()),
+            capacity,
+            data_file: None,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        tokenize(query).collect()
+    }
+
+    #[test]
+    fn extracts_text() {
+        assert_eq!(
+            tokenize("Oil's 2024 price-war, a Ü").collect::<Vec<_>>(),
+            ["oil", "2024", "price", "war"]
+        );
+        assert_eq!(
+            strip_tags("<a href=\"/x\">Tom &amp; Jerry</a>&nbsp;&lt;3"),
+            "Tom & Jerry <3"
+        );
+        assert_eq!(
+            section_of("/world/europe/story-2024-01-01/"),
+            "/world/europe/"
+        );
+        assert_eq!(section_of("story"), "/");
+    }
+
+    #[test]
+    fn snippets_start_near_the_match() {
+        let body = (0..100)
+            .map(|n| format!("w{n}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let document = document("/a/", "A", &body, "");
+        let snippet = document.snippet(&terms("w50"));
+        assert!(snippet.starts_with("… w40 "));
+        assert!(snippet.ends_with(" w69 …"));
+        assert_eq!(document.snippet(&terms("missing")), document.description);
+    }
+
+    #[test]
+    fn searches_in_order() {
+        let index = index(10);
+        index.insert(document(
+            "/world/a/",
+            "Oil prices",
+            "Oil and gas",
+            "2024-01-02",
+        ));
+        index.insert(document(
+            "/business/b/",
+            "Markets",
+            "Some oil",
+            "2024-01-03",
+        ));
+        index.insert(document("/world/c/", "Weather", "Rain", "2024-01-01"));
+
+        let search =
+            |order, filter: fn(&Document) -> bool| index.search(&terms("oil"), order, filter);
+        assert_eq!(
+            search(SearchOrder::Relevance, |_| true),
+            ["/world/a/", "/business/b/"]
+        );
+        assert_eq!(
+            search(SearchOrder::Newest, |_| true),
+            ["/business/b/", "/world/a/"]
+        );
+        assert_eq!(
+            search(SearchOrder::Oldest, |_| true),
+            ["/world/a/", "/business/b/"]
+        );
+        assert_eq!(
+            search(SearchOrder::Newest, |document| document.section
+                == "/world/"),
+            ["/world/a/"]
+        );
+
+        let articles = index.articles(
+            &["/business/b/".to_owned(), "/gone/".to_owned()],
+            &terms("oil"),
+        );
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].description, "Some oil");
+    }
+
+    #[test]
+    fn keeps_the_latest_documents() {
+        let index = index(2);
+        index.insert(document("/a/", "Oil", "", "1"));
+        index.insert(document("/b/", "Oil", "", "2"));
+        /* Unchanged documents keep their place, changed ones move to the back */
+        index.insert(document("/a/", "Oil", "", "1"));
+        index.insert(document("/b/", "Oil", "Updated", "2"));
+        index.insert(document("/c/", "Oil", "", "3"));
+
+        let inner = index.inner.read().unwrap();
+        assert_eq!(inner.order, ["/b/", "/c/"]);
+        assert_eq!(inner.postings["oil"].len(), 2);
+        assert!(inner.postings.contains_key("updated"));
+        assert!(inner.total_length > 0.0);
+    }
+
+    #[test]
+    fn corrupt_data_files_start_empty() {
+        let dir = std::env::temp_dir().join(format!("neuters-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+
+        fs::write(&path, "[{\"url\": ").unwrap();
+        assert!(load(&path, 10).documents.is_empty());
+        assert!(!path.exists());
+        assert!(path.with_extension("corrupt").exists());
+
+        let documents = [
+            document("/a/", "Oil", "", "1"),
+            document("/b/", "Gas", "", "2"),
+        ];
+        fs::write(&path, serde_json::to_vec(&documents).unwrap()).unwrap();
+        let inner = load(&path, 1);
+        assert_eq!(inner.order, ["/b/"]);
+        assert!(inner.postings.contains_key("gas") && !inner.postings.contains_key("oil"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
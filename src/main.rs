@@ -1,57 +1,70 @@
-mod api;
-mod client;
+mod activitypub;
+mod assets;
+mod front_page;
+mod hierarchy;
+mod local_index;
+mod pagination;
 mod render;
+mod resize;
+mod router;
 mod routes;
+mod search_guard;
 mod settings;
+#[cfg(test)]
+mod test_util;
 
-use std::collections::HashMap;
-
-use api::{error::ApiError, redirect::load_redirect};
-use client::Client;
-use routes::{
-    about::render_about,
-    article::render_article,
-    internet_news::render_legacy_article,
-    markets::render_market,
-    proxy::image_proxy,
-    search::{render_search, render_section, render_topic},
-    settings::handle_settings,
-};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-const CSS: &str = include_str!(concat!(env!("OUT_DIR"), "/main.css"));
+use activitypub::Federation;
+use front_page::FrontPage;
+use hierarchy::Hierarchy;
+use neuters::{
+    api::{self, error::ApiError},
+    client::{self, Client},
+};
+use resize::{OutputFormat, Resizer};
+use router::App;
 
 macro_rules! document {
-    ($title:expr, $content:expr, $( $head:expr )? ) => {
+    ($settings:expr, $title:expr, $content:expr, $( $head:expr )? ) => {
         maud::html! {
             (maud::DOCTYPE)
             html lang="en" {
                 head {
                     title { ($title) }
-                    link rel="stylesheet" href="/main.css?v=1";
+                    (crate::assets::head($settings))
                     meta name="viewport" content="width=device-width, initial-scale=1";
                     $( ($head) )?
                 }
-                body {
+                body class=(($settings).body_class()) {
                     main { ($content) }
                     footer { div {
-                        a href="/" { "Home" }
+                        a href=(($settings).link("/")) { "Home" }
+                        " - "
+                        a href=(($settings).link("/my")) { "Followed" }
                         " - "
-                        // a href="/search" { "Search" }
-                        // " - "
-                        a href="/settings" { "Settings" }
+                        a href=(($settings).link("/sections")) { "Sections" }
+                        " - "
+                        @if crate::routes::search::search_enabled() {
+                            a href=(($settings).link("/search")) { "Search" }
+                            " - "
+                        }
+                        a href=(($settings).link("/settings")) { "Settings" }
                         " - "
-                        a href="/about" { "About" } } }
+                        a href=(($settings).link("/about")) { "About" } } }
                 }
             }
         }
     };
 }
 pub(crate) use document;
-use settings::Settings;
+use settings::{query_token_redirect, token_prefix, url_token, with_token_location, Settings};
 
 pub struct Section {
     id: String,
     name: String,
+    /* None for the root of the hierarchy */
+    parent: Option<String>,
     children: Vec<SectionChild>,
 }
 
@@ -67,25 +80,67 @@ fn main() {
         .value_from_str("--address")
         .unwrap_or_else(|_| "127.0.0.1:13369".into());
 
-    let client: ureq::Agent = {
-        let certs = rustls_native_certs::load_native_certs().expect("Could not load certs!");
+    let public_url: Option<String> = pargs.opt_value_from_str("--public-url").unwrap_or(None);
+    let front_page_sections: String = pargs
+        .value_from_str("--front-page-sections")
+        .unwrap_or_else(|_| "/world/,/business/,/markets/,/technology/".into());
+    let front_page_size: u32 = pargs.value_from_str("--front-page-size").unwrap_or(5);
+    let enable_activitypub = pargs.contains("--activitypub");
+    let activitypub_data: Option<PathBuf> = pargs
+        .opt_value_from_str("--activitypub-data")
+        .unwrap_or(None);
+    let activitypub_interval: u64 = pargs
+        .value_from_str("--activitypub-interval")
+        .unwrap_or(600);
 
-        let mut root_store = rustls::RootCertStore::empty();
-        for cert in certs {
-            root_store
-                .add(&rustls::Certificate(cert.0))
-                .expect("Could not add cert!");
+    if let Some(path) = pargs
+        .opt_value_from_str::<_, PathBuf>("--settings-defaults")
+        .unwrap_or(None)
+    {
+        if let Err(err) = settings::load_defaults(&path) {
+            panic!("Failed to load settings defaults: {err}");
         }
-        let tls_config = std::sync::Arc::new(
-            rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(root_store)
-                .with_no_client_auth(),
-        );
+    }
+
+    let custom_css: Option<PathBuf> = pargs.opt_value_from_str("--custom-css").unwrap_or(None);
+    if let Err(err) = assets::load_custom_css(custom_css.as_deref()) {
+        panic!("Failed to load custom css: {err}");
+    }
 
+    let enable_search = pargs.contains("--search");
+    let search_rate: u32 = pargs.value_from_str("--search-rate").unwrap_or(10);
+    let real_ip_header: Option<String> =
+        pargs.opt_value_from_str("--real-ip-header").unwrap_or(None);
+
+    let enable_local_index = pargs.contains("--local-index");
+    let local_index_data: Option<PathBuf> = pargs
+        .opt_value_from_str("--local-index-data")
+        .unwrap_or(None);
+    let local_index_size: usize = pargs.value_from_str("--local-index-size").unwrap_or(10000);
+
+    search_guard::init(
+        enable_search,
+        enable_local_index,
+        search_rate,
+        real_ip_header,
+    );
+    local_index::init(enable_local_index, local_index_data, local_index_size);
+
+    let hierarchy_interval: u64 = pargs.value_from_str("--hierarchy-interval").unwrap_or(3600);
+
+    let resize_images = pargs.contains("--resize-images");
+    let resize_format: OutputFormat = pargs
+        .value_from_str("--resize-format")
+        .unwrap_or(OutputFormat::Webp);
+    let resize_quality: u8 = pargs.value_from_str("--resize-quality").unwrap_or(75);
+    let resize_cache_size: usize = pargs.value_from_str("--resize-cache-size").unwrap_or(64);
+
+    let tls_config = client::tls_config();
+
+    let client: ureq::Agent = {
         let mut client_builder = ureq::AgentBuilder::new();
 
-        client_builder = client_builder.tls_config(tls_config).redirects(0);
+        client_builder = client_builder.tls_config(tls_config.clone()).redirects(0);
 
         #[cfg(debug_assertions)]
         {
@@ -126,118 +181,82 @@ fn main() {
         headers.push(("Cookie".to_string(), cookie));
     };
 
-    let client = Client::new(client, headers);
-
-    println!("Fetching site hierarchy");
-    let mut sections_by_id: HashMap<String, Section> = std::collections::HashMap::new();
-    if let Ok(section) = api::section::fetch_site_hierarchy_by_name(&client) {
-        let mut queue = vec![section];
-        while let Some(section) = queue.pop() {
-            let children = section
-                .children
-                .as_deref()
-                .unwrap_or_default()
-                .iter()
-                .map(|s| SectionChild {
-                    id: s.id.clone(),
-                    name: s.name.clone(),
-                })
-                .collect();
-            for child in section.children.unwrap_or_default() {
-                queue.push(child);
-            }
-            let node = Section {
-                id: section.id.clone(),
-                name: section.name.clone(),
-                children,
-            };
-            sections_by_id.insert(section.id, node);
+    let client = Arc::new(Client::new(client, headers));
+
+    let hierarchy = Arc::new(Hierarchy::load(&client));
+    hierarchy::spawn_refresh(
+        hierarchy.clone(),
+        client.clone(),
+        Duration::from_secs(hierarchy_interval),
+    );
+
+    let federation = match (enable_activitypub, public_url.clone()) {
+        (true, Some(public_url)) => {
+            let agent = ureq::AgentBuilder::new()
+                .tls_config(tls_config)
+                .timeout(Duration::from_secs(30))
+                .build();
+            let federation = Arc::new(Federation::new(&public_url, agent, activitypub_data));
+            activitypub::delivery::spawn(
+                federation.clone(),
+                client.clone(),
+                Duration::from_secs(activitypub_interval),
+            );
+            println!("ActivityPub enabled for {}", federation.host);
+            Some(federation)
         }
-        println!("Fetched site hierarchy");
-        println!("Sections: {}", sections_by_id.len());
-    } else {
-        eprintln!("Failed to fetch site hierarchy");
+        (true, None) => {
+            eprintln!("ActivityPub requires --public-url, disabling it");
+            None
+        }
+        _ => None,
+    };
+
+    resize::init(resize_images);
+    let resizer = Resizer::new(
+        resize_images,
+        resize_format,
+        resize_quality,
+        resize_cache_size * 1024 * 1024,
+    );
+
+    let app = App {
+        client,
+        hierarchy,
+        federation,
+        resizer,
+        public_url,
+        front_page: FrontPage::new(
+            front_page_sections
+                .split(',')
+                .map(|section| section.trim().to_owned())
+                .filter(|section| !section.is_empty())
+                .collect(),
+            front_page_size,
+        ),
     };
 
     println!("Listening on http://{}", list_address);
     rouille::start_server(list_address, move |request| {
-        let path = request.url();
-        let settings = Settings::from_request(request);
-
-        let response = match path.as_str() {
-            "/" | "/home" | "/world/" => {
-                let offset = request
-                    .get_param("offset")
-                    .map_or(0, |s| s.parse::<u32>().unwrap_or(0));
-                let section = sections_by_id
-                    .get("/world/")
-                    .unwrap_or_else(|| panic!("Section 'world' not found"));
-
-                render_section(&client, section, offset, 8)
-            }
-            "/about" => render_about(),
-            "/settings" => return handle_settings(request, &settings),
-            "/search" | "/search/" => render_search(&client, request),
-            "/main.css" => {
-                return rouille::Response {
-                    status_code: 200,
-                    headers: vec![
-                        ("Content-Type".into(), "text/css".into()),
-                        ("Cache-Control".into(), "public, max-age=31536000".into()),
-                    ],
-                    data: rouille::ResponseBody::from_string(CSS),
-                    upgrade: None,
-                };
-            }
-            "/favicon.ico" => Err(ApiError::Empty),
-            _ => {
-                if let Some(section) = sections_by_id.get(path.as_str()) {
-                    let offset = request
-                        .get_param("offset")
-                        .map_or(0, |s| s.parse::<u32>().unwrap_or(0));
-                    render_section(&client, section, offset, 8)
-                } else if path.starts_with("/authors/") {
-                    let offset = request
-                        .get_param("offset")
-                        .map_or(0, |s| s.parse::<u32>().unwrap_or(0));
-                    render_topic(&client, &path, offset, 20)
-                } else if let Some(path) = path.strip_prefix("/topic/") {
-                    let full_path = format!("https://www.reuters.com/topic/{path}");
-                    let redirect = load_redirect(&client, &full_path);
-                    match redirect {
-                        Ok((status, location)) => {
-                            return rouille::Response {
-                                status_code: status,
-                                headers: vec![
-                                    ("Location".into(), strip_prefix(&location).to_owned().into()),
-                                    ("Cache-Control".into(), "public, max-age=31536000".into()),
-                                ],
-                                data: rouille::ResponseBody::empty(),
-                                upgrade: None,
-                            };
-                        }
-                        Err(err) => Err(err),
-                    }
-                } else if path.starts_with("/article/") {
-                    match render_legacy_article(&client, &path) {
-                        Ok(result) => result,
-                        Err(response) => return response,
-                    }
-                } else if let Some(path) = path.strip_prefix("/companies/") {
-                    render_market(&client, path)
-                } else if let Some(path) = path.strip_prefix("/markets/companies/") {
-                    render_market(&client, path)
-                } else if let Some(path) = request.raw_url().strip_prefix("/proxy/") {
-                    return image_proxy(&client, request, path);
-                } else {
-                    render_article(&client, &path, &settings)
-                }
-            }
+        /* Settings can also travel in the URL for clients without cookies */
+        if let Some(response) = query_token_redirect(request) {
+            return response;
+        }
+        let token = url_token(request).map(str::to_owned);
+        let stripped = token
+            .as_ref()
+            .and_then(|token| request.remove_prefix(&token_prefix(token)));
+        let request = stripped.as_ref().unwrap_or(request);
+
+        let settings = match &token {
+            Some(token) => Settings::from_token(token),
+            None => Settings::from_request(request),
         };
 
-        match response {
-            Ok(body) => rouille::Response::html(body),
-            Err(err) => render_api_error(&err, &path, &settings),
+        let response = router::dispatch(&app, request, &settings, token.as_deref());
+        match &token {
+            Some(token) => with_token_location(response, token),
+            None => response,
         }
     });
 }
@@ -270,13 +289,14 @@ fn render_api_error(err: &ApiError, path: &str, settings: &Settings) -> rouille:
         ApiError::Empty => (maud::html!(), maud::html!()),
         ApiError::Redirect(_, location) => {
             let location = strip_prefix(location);
+            let target = settings.link(location);
             (
                 maud::html! {
-                    meta http-equiv="refresh" content=(format!("{}; url={}", settings.redirect_timer, location));
+                    meta http-equiv="refresh" content=(format!("{}; url={}", settings.redirect_timer, target));
                     link rel="canonical" href=(location);
                 },
                 maud::html! {
-                    p { "Redirecting to " (location) " in " (settings.redirect_timer) " seconds. Or click " a href=(location) { "here" } " to follow the link directly." }
+                    p { "Redirecting to " (location) " in " (settings.redirect_timer) " seconds. Or click " a href=(target) { "here" } " to follow the link directly." }
                 },
             )
         }
@@ -293,13 +313,14 @@ fn render_api_error(err: &ApiError, path: &str, settings: &Settings) -> rouille:
     };
 
     let doc = document!(
+        settings,
         &title,
         maud::html! {
             h1 { (&title) }
             p { "You tried to access \"" (path) "\"" }
             (details)
-            p { a href="/" { "Go home" } }
-            p { a href=(path) { "Try again" } }
+            p { a href=(settings.link("/")) { "Go home" } }
+            p { a href=(settings.link(path)) { "Try again" } }
         },
         head
     );
@@ -0,0 +1,369 @@
+use chrono::{DateTime, Utc};
+use maud::{html, Markup};
+use neuters::api::{
+    common::{Article, Articles},
+    error::ApiResult,
+};
+use rouille::Request;
+
+/* Page sizes a listing endpoint accepts, anything else falls back to the default.
+ * `max` bounds every upstream request, anchored fetches included. */
+pub struct PageSizes {
+    pub default: u32,
+    pub choices: &'static [u32],
+    pub max: u32,
+}
+
+pub const SECTION_SIZES: PageSizes = PageSizes {
+    default: 8,
+    choices: &[8, 20, 50],
+    max: 100,
+};
+
+pub const TOPIC_SIZES: PageSizes = PageSizes {
+    default: 20,
+    choices: &[10, 20, 50],
+    max: 100,
+};
+
+/* The search endpoint rejects pages larger than 20 */
+pub const SEARCH_SIZES: PageSizes = PageSizes {
+    default: 20,
+    choices: &[10, 20],
+    max: 20,
+};
+
+/* Stories fetched around the requested offset, so the anchor survives a few new or removed ones */
+const ANCHOR_SLACK: u32 = 5;
+
+/* Gives up re-anchoring when more stories arrived than this many windows cover */
+const MAX_SHIFTS: u32 = 3;
+
+pub struct Page {
+    pub offset: u32,
+    pub size: u32,
+    /* Publish time and url of the last story on the previous page, the url tells apart
+     * stories published at the same time */
+    pub before: Option<DateTime<Utc>>,
+    pub last: Option<String>,
+    pub sizes: &'static PageSizes,
+}
+
+fn published(article: &Article) -> Option<DateTime<Utc>> {
+    article.published_time.parse().ok()
+}
+
+/* Percent-encodes everything but unreserved characters */
+pub fn encode_param(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/* Stories at the start of `list` that were shown before: everything published at or after
+ * the anchor, or only up to the last story shown if it is among them */
+fn shown(list: &[Article], before: DateTime<Utc>, last: Option<&str>) -> usize {
+    let newer = list
+        .iter()
+        .take_while(|article| published(article).is_none_or(|time| time >= before))
+        .count();
+    last.and_then(|last| {
+        list[..newer]
+            .iter()
+            .position(|article| article.canonical_url == last)
+    })
+    .map_or(newer, |index| index + 1)
+}
+
+impl Page {
+    /* Reads size (or the older steps), offset or page, and the before anchor */
+    pub fn from_request(request: &Request, sizes: &'static PageSizes) -> Self {
+        let size = request
+            .get_param("size")
+            .or_else(|| request.get_param("steps"))
+            .and_then(|size| size.parse().ok())
+            .filter(|size| sizes.choices.contains(size))
+            .unwrap_or(sizes.default);
+        let offset = match request.get_param("offset") {
+            Some(offset) => offset.parse().unwrap_or(0),
+            None => request
+                .get_param("page")
+                .and_then(|page| page.parse::<u32>().ok())
+                .map_or(0, |page| page.saturating_sub(1).saturating_mul(size)),
+        };
+        let before = request
+            .get_param("before")
+            .and_then(|before| before.parse().ok());
+
+        Self {
+            offset,
+            size,
+            before,
+            last: request.get_param("last"),
+            sizes,
+        }
+    }
+
+    /* Without an anchor this is a plain offset fetch. With one, stories shown before it are
+     * dropped, so stories arriving between two pages don't repeat on the
+     * next one. The window moves on until it holds a full page after the anchor or
+     * reaches the end of the listing. Returns the upstream offset of the first story shown. */
+    pub fn fetch(
+        &self,
+        fetch: impl Fn(u32, u32) -> ApiResult<Articles>,
+    ) -> ApiResult<(u32, Articles)> {
+        let Some(before) = self.before else {
+            return Ok((self.offset, fetch(self.offset, self.size)?));
+        };
+
+        let mut start = self.offset.saturating_sub(ANCHOR_SLACK);
+        for _ in 0..MAX_SHIFTS {
+            let window = (self.offset.max(start) - start)
+                .saturating_add(self.size + ANCHOR_SLACK)
+                .min(self.sizes.max);
+            let mut articles = fetch(start, window)?;
+            let list = articles.articles.take().unwrap_or_default().into_vec();
+            let fetched = list.len() as u32;
+            let newer = shown(&list, before, self.last.as_deref()) as u32;
+
+            if fetched - newer >= self.size || fetched < window {
+                let page: Vec<Article> = list
+                    .into_iter()
+                    .skip(newer as usize)
+                    .take(self.size as usize)
+                    .collect();
+                articles.articles = (!page.is_empty()).then(|| page.into_boxed_slice());
+                return Ok((start + newer, articles));
+            }
+            start = start.saturating_add(newer);
+        }
+
+        Ok((self.offset, fetch(self.offset, self.size)?))
+    }
+}
+
+/* First, previous, next and last links, the page sizes and a jump-to-page form.
+ * `url` already ends in ? or &, `hidden` repeats its parameters in the form. Only
+ * listings sorted newest first can `anchor` the next page. */
+pub fn render_pagination(
+    url: &str,
+    hidden: &[(&str, &str)],
+    page: &Page,
+    anchor: bool,
+    offset: u32,
+    articles: &[Article],
+    total: u32,
+) -> Markup {
+    let size = page.size;
+    let count = articles.len() as u32;
+    let link = |offset: u32| format!("{url}size={size}&offset={offset}");
+    let pages = total.div_ceil(size).max(1);
+    let current = (offset / size + 1).min(pages);
+    let last_offset = (pages - 1) * size;
+
+    let has_prev = offset > 0;
+    let has_next = offset + count < total;
+    let first_page = has_prev.then(|| link(0));
+    let prev_page = has_prev.then(|| link(offset.saturating_sub(size)));
+    /* Only the next page is anchored, the others are plain offsets */
+    let next_page = has_next.then(|| {
+        let next = link(offset + count);
+        let last = articles.last().filter(|_| anchor);
+        match last.and_then(|article| Some((published(article)?, article))) {
+            Some((time, article)) => format!(
+                "{next}&before={}&last={}",
+                time.format("%Y-%m-%dT%H:%M:%S%.fZ"),
+                encode_param(&article.canonical_url)
+            ),
+            None => next,
+        }
+    });
+    let last_page = (has_next && last_offset > offset).then(|| link(last_offset));
+
+    html! {
+        div.nav {
+            a href=[first_page] title="First page" { "«" }
+            a href=[prev_page] title="Previous page" { "<" }
+            ((offset + 1)) " to " ((offset + count)) " of " (total)
+            a href=[next_page] title="Next page" { ">" }
+            a href=[last_page] title="Last page" { "»" }
+        }
+        div.nav {
+            form.jump {
+                @for (name, value) in hidden {
+                    input type="hidden" name=(name) value=(value);
+                }
+                input type="hidden" name="size" value=(size);
+                label {
+                    "Page "
+                    input type="number" name="page" min="1" max=(pages) value=(current);
+                    " of " (pages)
+                }
+                button type="submit" { "Go" }
+            }
+            " Per page: "
+            @for choice in page.sizes.choices {
+                @if *choice == size {
+                    b { (choice) }
+                } @else {
+                    a href=(format!("{url}size={choice}&offset={}", offset / choice * choice)) { (choice) }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::test_util::{article, articles};
+
+    fn page(query: &str, sizes: &'static PageSizes) -> Page {
+        let request = Request::fake_http("GET", format!("/world/?{query}"), vec![], vec![]);
+        Page::from_request(&request, sizes)
+    }
+
+    #[test]
+    fn reads_requests() {
+        let plain = page("", &SECTION_SIZES);
+        assert_eq!((plain.offset, plain.size, plain.before), (0, 8, None));
+
+        let sized = page("size=20&page=3", &SECTION_SIZES);
+        assert_eq!((sized.offset, sized.size), (40, 20));
+        assert_eq!(page("steps=50&offset=7", &SECTION_SIZES).size, 50);
+        /* Sizes other endpoints accept fall back to the default */
+        assert_eq!(page("size=50", &SEARCH_SIZES).size, 20);
+        assert_eq!(page("offset=x&page=2", &SECTION_SIZES).offset, 0);
+        assert_eq!(
+            page("page=4294967295&size=50", &SECTION_SIZES).offset,
+            u32::MAX
+        );
+
+        let anchored = page("before=2024-01-01T12:00:00Z", &SECTION_SIZES);
+        assert_eq!(
+            anchored.before,
+            Some("2024-01-01T12:00:00Z".parse().unwrap())
+        );
+        assert_eq!(page("before=yesterday", &SECTION_SIZES).before, None);
+    }
+
+    /* Stories published an hour apart, newest first, starting at `newest` hours */
+    fn listing(newest: i64, count: i64) -> Vec<Article> {
+        (0..count)
+            .map(|n| {
+                let time = DateTime::from_timestamp((newest - n) * 3600, 0).unwrap();
+                article(&format!("/story-{}/", newest - n), &time.to_rfc3339())
+            })
+            .collect()
+    }
+
+    /* Fetches from `list`, recording the requested windows */
+    fn fetch(page: &Page, list: &[Article]) -> (u32, Vec<String>, Vec<(u32, u32)>) {
+        let requests = RefCell::new(vec![]);
+        let (offset, articles) = page
+            .fetch(|offset, size| {
+                requests.borrow_mut().push((offset, size));
+                let start = (offset as usize).min(list.len());
+                let end = start.saturating_add(size as usize).min(list.len());
+                Ok(articles(list[start..end].into(), Some(list.len() as u32)))
+            })
+            .unwrap();
+        let urls = articles
+            .articles
+            .unwrap_or_default()
+            .iter()
+            .map(|article| article.canonical_url.clone())
+            .collect();
+        (offset, urls, requests.into_inner())
+    }
+
+    fn anchored(offset: u32, size: u32, before: i64, sizes: &'static PageSizes) -> Page {
+        Page {
+            offset,
+            size,
+            before: DateTime::from_timestamp(before * 3600, 0),
+            last: None,
+            sizes,
+        }
+    }
+
+    #[test]
+    fn anchors_survive_new_stories() {
+        /* Three stories arrived since the first page ended at story 92 */
+        let list = listing(103, 100);
+        let (offset, urls, _) = fetch(&anchored(8, 4, 92, &SECTION_SIZES), &list);
+        assert_eq!(offset, 12);
+        assert_eq!(
+            urls,
+            ["/story-91/", "/story-90/", "/story-89/", "/story-88/"]
+        );
+
+        let (offset, urls, requests) = fetch(&page("offset=8&size=8", &SECTION_SIZES), &list);
+        assert_eq!((offset, urls.len()), (8, 8));
+        assert_eq!(requests, [(8, 8)]);
+    }
+
+    #[test]
+    fn anchors_tell_apart_stories_published_together() {
+        /* Stories 92 and 91 share a publish time, the first page ended between them */
+        let mut list = listing(100, 20);
+        list[9].published_time = list[8].published_time.clone();
+        let next = render_pagination(
+            "/world/?",
+            &[],
+            &page("size=8", &SECTION_SIZES),
+            true,
+            0,
+            &list[..9],
+            20,
+        )
+        .into_string();
+        let query = next
+            .split("href=\"/world/?")
+            .find(|link| link.contains("before="))
+            .and_then(|link| link.split('"').next())
+            .unwrap()
+            .replace("&amp;", "&");
+        assert!(query.ends_with("&last=%2Fstory-92%2F"), "{query}");
+
+        /* Story 101 arrived since */
+        list.insert(0, listing(101, 1).remove(0));
+        let (offset, urls, _) = fetch(&page(&query, &SECTION_SIZES), &list);
+        assert_eq!(offset, 10);
+        assert_eq!(urls[0], "/story-91/");
+    }
+
+    #[test]
+    fn only_newest_first_is_anchored() {
+        let list = listing(100, 8);
+        let page = page("", &SECTION_SIZES);
+        let next = |anchor| {
+            let html =
+                render_pagination("/world/?", &[], &page, anchor, 0, &list, 20).into_string();
+            html.contains("offset=8&amp;before=")
+        };
+        assert!(next(true));
+        assert!(!next(false));
+    }
+
+    #[test]
+    fn windows_stay_within_limits() {
+        /* Every story is newer than the anchor, the window moves on without underflowing */
+        let list = listing(100, 100);
+        let (offset, urls, requests) = fetch(&anchored(8, 20, 0, &SEARCH_SIZES), &list);
+        assert_eq!((offset, urls.len()), (8, 20));
+        assert!(requests.iter().all(|(_, size)| *size <= SEARCH_SIZES.max));
+
+        /* Past the end of the listing */
+        let (_, urls, _) = fetch(&anchored(u32::MAX, 50, 0, &SECTION_SIZES), &list);
+        assert!(urls.is_empty());
+    }
+}
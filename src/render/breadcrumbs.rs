@@ -0,0 +1,81 @@
+use maud::{html, Markup};
+
+use crate::{hierarchy::SectionMap, settings::Settings};
+
+/* Sections above (and including) `id`, outermost first, without the root.
+ * A trail can't be longer than the map, parent cycles stop there. */
+pub fn section_trail<'a>(sections: &'a SectionMap, id: &str) -> Vec<(&'a str, &'a str)> {
+    let mut trail = vec![];
+    let mut current = sections.get(id);
+    while let Some(section) = current {
+        if section.parent.is_none() || trail.len() == sections.len() {
+            break;
+        }
+        trail.push((section.id.as_str(), section.name.as_str()));
+        current = section
+            .parent
+            .as_ref()
+            .and_then(|parent| sections.get(parent));
+    }
+    trail.reverse();
+    trail
+}
+
+/* Articles live below their section, i.e. /world/europe/some-story-2024-01-01/ */
+pub fn article_trail<'a>(sections: &'a SectionMap, path: &str) -> Vec<(&'a str, &'a str)> {
+    let path = crate::strip_prefix(path).trim_end_matches('/');
+    let Some((parent, _)) = path.rsplit_once('/') else {
+        return vec![];
+    };
+    section_trail(sections, &format!("{parent}/"))
+}
+
+pub fn render_breadcrumbs(trail: &[(&str, &str)], settings: &Settings) -> Markup {
+    html! {
+        @if !trail.is_empty() {
+            nav.breadcrumbs {
+                a href=(settings.link("/sections")) { "Sections" }
+                @for (id, name) in trail {
+                    " › "
+                    a href=(settings.link(id)) { (name) }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{section, sections};
+
+    #[test]
+    fn trails() {
+        let sections = sections();
+        assert_eq!(
+            section_trail(&sections, "/world/europe/"),
+            [("/world/", "World"), ("/world/europe/", "Europe")]
+        );
+        assert!(section_trail(&sections, "/").is_empty());
+        assert!(section_trail(&sections, "/unknown/").is_empty());
+
+        let cycle: SectionMap = [
+            section("/a/", "A", Some("/b/")),
+            section("/b/", "B", Some("/a/")),
+        ]
+        .into_iter()
+        .map(|section| (section.id.clone(), section))
+        .collect();
+        assert_eq!(section_trail(&cycle, "/a/").len(), 2);
+
+        assert_eq!(
+            article_trail(&sections, "/world/europe/some-story-2024-01-01/"),
+            [("/world/", "World"), ("/world/europe/", "Europe")]
+        );
+        assert_eq!(
+            article_trail(&sections, "https://www.reuters.com/world/story-2024-01-01/"),
+            [("/world/", "World")]
+        );
+        assert!(article_trail(&sections, "/unknown/story-2024-01-01/").is_empty());
+    }
+}
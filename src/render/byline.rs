@@ -1,24 +1,24 @@
-use crate::api::common::Topic;
+use crate::{api::common::Topic, settings::Settings};
 use std::fmt::Write;
 
-pub fn render_byline(authors: &[Topic]) -> String {
+pub fn render_byline(authors: &[Topic], settings: &Settings) -> String {
     match authors.len() {
         0 => "".to_string(),
-        1 => format_author(&authors[0]),
+        1 => format_author(&authors[0], settings),
         author_count => {
             /* Chain author names together */
             let mut byline = "By ".to_string();
 
             for author in authors[..author_count - 2].iter() {
-                byline.push_str(&format_author(author));
+                byline.push_str(&format_author(author, settings));
                 byline.push_str(", ");
             }
 
             let _ = write!(
                 byline,
                 "{} and {}",
-                format_author(&authors[author_count - 2]),
-                format_author(&authors[author_count - 1])
+                format_author(&authors[author_count - 2], settings),
+                format_author(&authors[author_count - 1], settings)
             );
 
             byline
@@ -26,9 +26,9 @@ pub fn render_byline(authors: &[Topic]) -> String {
     }
 }
 
-pub fn format_author(author: &Topic) -> String {
+pub fn format_author(author: &Topic, settings: &Settings) -> String {
     if let Some(url) = &author.topic_url {
-        format!("<a href=\"{}\">{}</a>", url, author.byline)
+        format!("<a href=\"{}\">{}</a>", settings.link(url), author.byline)
     } else {
         author.byline.clone()
     }
@@ -0,0 +1,19 @@
+use maud::{html, Markup};
+
+use crate::settings::Settings;
+
+/* Posts to /follow, which stores the change like the settings page and returns here */
+pub fn render_follow_button(path: &str, settings: &Settings) -> Markup {
+    let following = settings.follows.contains(path);
+
+    html! {
+        form.follow method="POST" action=(settings.link("/follow")) {
+            input type="hidden" name="path" value=(path);
+            @if following {
+                button type="submit" name="action" value="unfollow" { "Unfollow" }
+            } @else {
+                button type="submit" name="action" value="follow" { "Follow" }
+            }
+        }
+    }
+}
@@ -1,42 +1,308 @@
-use crate::{api::common::Image, routes::proxy, settings::Settings};
+use maud::{html, Markup};
+use serde_json::Value;
 
-const RESIZE_STEPS: [u16; 6] = [480, 640, 720, 960, 1080, 1200];
+use crate::{
+    api::common::{Credits, Image},
+    resize,
+    routes::proxy,
+    settings::{ImagePlaceholders, Settings},
+};
 
-pub fn render_image(thumbnail: &Image, settings: &Settings) -> maud::Markup {
-    let resizer_url = &thumbnail.resizer_url;
+pub const RESIZE_STEPS: [u16; 6] = [480, 640, 720, 960, 1080, 1200];
 
-    let url = if settings.proxy_images {
-        if let Some(base_path) = proxy::strip_prefix(resizer_url) {
-            format!("/proxy/{base_path}")
-        } else {
-            return maud::html! {
-                p {
-                    i { "Proxy requested but no supported link found!" }
-                }
-            };
-        }
-    } else {
-        resizer_url.to_string()
+/* Smallest resizer step, thumbnails are shown at a fraction of that */
+const THUMBNAIL_WIDTH: u16 = RESIZE_STEPS[0];
+
+/* Inline images share the thumbnail format, graphics only carry a url and description */
+pub fn image_from_element(content: &Value) -> Option<Image> {
+    match content["type"].as_str()? {
+        "image" => serde_json::from_value(content.clone()).ok(),
+        "graphic" => Some(Image {
+            url: content["url"].as_str().map(str::to_owned),
+            caption: content["description"].as_str().map(str::to_owned),
+            alt_text: content["description"].as_str().map(str::to_owned),
+            width: content["width"].as_u64().and_then(|w| w.try_into().ok()),
+            height: content["height"].as_u64().and_then(|h| h.try_into().ok()),
+            ..Default::default()
+        }),
+        _ => None,
+    }
+}
+
+/* Url the client should load, None if proxying was requested but the proxy won't serve it */
+fn source_url(image: &Image, settings: &Settings) -> Option<(String, bool)> {
+    let (url, resizable) = match (&image.resizer_url, &image.url) {
+        (Some(url), _) => (url, true),
+        (None, Some(url)) => (url, false),
+        (None, None) => return None,
     };
+
+    if settings.proxy_images {
+        proxy::strip_prefix(url)
+            .filter(|path| proxy::is_allowed_path(path))
+            .map(|path| (format!("/proxy/{path}"), resizable))
+    } else {
+        Some((url.clone(), resizable))
+    }
+}
+
+fn srcset(url: &str, max_width: Option<u16>) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
     let mut srcset = String::new();
     for width in RESIZE_STEPS {
-        if let Some(w) = thumbnail.width {
+        if let Some(w) = max_width {
             if width > w {
                 break;
             }
         }
-        srcset.push_str(&format!("{url}&width={width}&quality=80 {width}w,"));
+        srcset.push_str(&format!(
+            "{url}{separator}width={width}&quality=80 {width}w,"
+        ));
+    }
+    srcset
+}
+
+/* Placeholders are computed by the proxy, which only does so when processing images */
+fn placeholders(settings: &Settings) -> Option<ImagePlaceholders> {
+    (settings.proxy_images
+        && settings.image_placeholders != ImagePlaceholders::Off
+        && resize::enabled())
+    .then_some(settings.image_placeholders)
+}
+
+fn credit(credits: &Credits) -> Option<String> {
+    let names: Vec<&str> = credits
+        .by
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|c| c.byline.as_deref().or(c.name.as_deref()))
+        .collect();
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+/* Thumbnails are small enough to load right away. Placeholders only stand in for images
+ * that can't be resized, which would otherwise load in full. */
+fn thumbnail_src(url: String, resizable: bool, placeholders: Option<ImagePlaceholders>) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    if resizable {
+        format!("{url}{separator}width={THUMBNAIL_WIDTH}&quality=80")
+    } else if let Some(placeholders) = placeholders {
+        format!("{url}{separator}placeholder={}", placeholders.as_str())
+    } else {
+        url
+    }
+}
+
+/* Small preview linking to the story, left out entirely when images aren't embedded */
+pub fn render_thumbnail(image: &Image, href: &str, settings: &Settings) -> Markup {
+    let Some((url, resizable)) = source_url(image, settings) else {
+        return html!();
+    };
+    if !settings.embed_images {
+        return html!();
+    }
+
+    let src = thumbnail_src(url, resizable, placeholders(settings));
+
+    html! {
+        a.thumbnail href=(href) {
+            img src=(src) loading="lazy"
+                width=[image.width] height=[image.height]
+                alt=(image.alt_text.as_deref().unwrap_or_default());
+        }
     }
+}
+
+pub fn render_image(image: &Image, settings: &Settings) -> Markup {
+    let Some((url, resizable)) = source_url(image, settings) else {
+        return html! {
+            p {
+                i { "Proxy requested but no supported link found!" }
+            }
+        };
+    };
 
-    maud::html! {
+    if !settings.embed_images {
+        return html! {
+            p {
+                i {
+                    "Embedding images is disabled. Navigate to the original resource or change the settings to enable it."
+                }
+            }
+            p {
+                a href=(url) { "Image" }
+            }
+        };
+    }
+
+    let credit = image.credits.as_ref().and_then(credit);
+
+    if let Some(placeholders) = placeholders(settings) {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let placeholder = format!("{url}{separator}placeholder={}", placeholders.as_str());
+        return html! {
+            figure.placeholder {
+                img src=(placeholder)
+                    width=[image.width] height=[image.height]
+                    alt=(image.alt_text.as_deref().unwrap_or_default());
+                figcaption {
+                    a href=(url) { "Load full image" }
+                    @if let Some(caption) = &image.caption {
+                        " " i { (caption) }
+                    }
+                    @if let Some(credit) = &credit {
+                        " "
+                        span.credit { "Photo: " (credit) }
+                    }
+                }
+            }
+        };
+    }
+
+    let srcset = resizable.then(|| srcset(&url, image.width));
+
+    html! {
         figure {
             img src=(url)
-                srcset=(srcset)
-                width=[thumbnail.width] height=[thumbnail.height]
-                alt="";
-            @if let Some(caption) = &thumbnail.caption {
-                figcaption { i { (caption) } }
+                srcset=[srcset]
+                width=[image.width] height=[image.height]
+                alt=(image.alt_text.as_deref().unwrap_or_default());
+            @if image.caption.is_some() || credit.is_some() {
+                figcaption {
+                    @if let Some(caption) = &image.caption {
+                        i { (caption) }
+                    }
+                    @if let Some(credit) = credit {
+                        " "
+                        span.credit { "Photo: " (credit) }
+                    }
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn image(resizer_url: Option<&str>, url: Option<&str>) -> Image {
+        Image {
+            resizer_url: resizer_url.map(str::to_owned),
+            url: url.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn source_urls() {
+        let settings = Settings::default();
+        let resized = image(
+            Some("https://www.reuters.com/resizer/v2/a.jpg?auth=x"),
+            Some("https://example.com/a.jpg"),
+        );
+        assert_eq!(
+            source_url(&resized, &settings),
+            Some(("/proxy/resizer/v2/a.jpg?auth=x".to_owned(), true))
+        );
+
+        /* Images outside of reuters.com can't be proxied */
+        let foreign = image(None, Some("https://example.com/a.jpg"));
+        assert_eq!(source_url(&foreign, &settings), None);
+
+        let direct = Settings {
+            proxy_images: false,
+            ..Settings::default()
+        };
+        assert_eq!(
+            source_url(&foreign, &direct),
+            Some(("https://example.com/a.jpg".to_owned(), false))
+        );
+    }
+
+    #[test]
+    fn srcset_stops_at_image_width() {
+        assert_eq!(
+            srcset("/proxy/a.jpg?auth=x", Some(700)),
+            "/proxy/a.jpg?auth=x&width=480&quality=80 480w,\
+             /proxy/a.jpg?auth=x&width=640&quality=80 640w,"
+        );
+        assert_eq!(
+            srcset("/a.jpg", None).split_terminator(',').count(),
+            RESIZE_STEPS.len()
+        );
+    }
+
+    #[test]
+    fn no_placeholders_without_processing() {
+        let settings = Settings {
+            embed_images: true,
+            image_placeholders: ImagePlaceholders::Blur,
+            ..Settings::default()
+        };
+        let image = image(Some("https://www.reuters.com/resizer/v2/a.jpg"), None);
+        let thumbnail = render_thumbnail(&image, "/world/a/", &settings).into_string();
+        assert!(thumbnail.contains("/proxy/resizer/v2/a.jpg?width=480"));
+        assert!(!thumbnail.contains("placeholder"));
+        assert!(!render_image(&image, &settings)
+            .into_string()
+            .contains("placeholder"));
+    }
+
+    #[test]
+    fn thumbnails_are_resized() {
+        let url = "/proxy/resizer/v2/a.jpg?auth=x".to_owned();
+        assert_eq!(
+            thumbnail_src(url.clone(), true, Some(ImagePlaceholders::Blur)),
+            "/proxy/resizer/v2/a.jpg?auth=x&width=480&quality=80"
+        );
+        assert_eq!(
+            thumbnail_src(
+                "/proxy/a.jpg".to_owned(),
+                false,
+                Some(ImagePlaceholders::Color)
+            ),
+            "/proxy/a.jpg?placeholder=color"
+        );
+        assert_eq!(thumbnail_src(url.clone(), false, None), url);
+    }
+
+    #[test]
+    fn graphics_become_images() {
+        let graphic = json!({
+            "type": "graphic",
+            "url": "https://www.reuters.com/graphics/a.png",
+            "description": "A chart",
+            "width": 800,
+        });
+        let image = image_from_element(&graphic).unwrap();
+        assert_eq!(image.alt_text.as_deref(), Some("A chart"));
+        assert_eq!(image.width, Some(800));
+        assert!(image_from_element(&json!({ "type": "paragraph" })).is_none());
+    }
+
+    #[test]
+    fn graphics_are_only_proxied_where_allowed() {
+        let settings = Settings {
+            embed_images: true,
+            ..Settings::default()
+        };
+        for (url, proxied) in [
+            ("https://www.reuters.com/graphics/a.png", false),
+            ("https://www.reuters.com/arc/outboundfeeds/a.png", true),
+        ] {
+            let graphic = image_from_element(&json!({ "type": "graphic", "url": url })).unwrap();
+            let source = source_url(&graphic, &settings);
+            assert_eq!(source.is_some(), proxied, "{url}");
+            if let Some((src, _)) = source {
+                assert!(proxy::is_allowed_path(src.strip_prefix("/proxy/").unwrap()));
+            }
+
+            let rendered = render_image(&graphic, &settings).into_string();
+            assert_eq!(rendered.contains("/proxy/"), proxied, "{rendered}");
+        }
+    }
+}
@@ -1,25 +1,25 @@
 use std::fmt::Write;
 
-use crate::api::legacy_article::LegacyArticleAuthor;
+use crate::{api::legacy_article::LegacyArticleAuthor, settings::Settings};
 
-pub fn render_byline(authors: &[LegacyArticleAuthor]) -> String {
+pub fn render_byline(authors: &[LegacyArticleAuthor], settings: &Settings) -> String {
     match authors.len() {
         0 => "".to_string(),
-        1 => format_author(&authors[0]),
+        1 => format_author(&authors[0], settings),
         author_count => {
             /* Chain author names together */
             let mut byline = "By ".to_string();
 
             for author in authors[..author_count - 2].iter() {
-                byline.push_str(&format_author(author));
+                byline.push_str(&format_author(author, settings));
                 byline.push_str(", ");
             }
 
             let _ = write!(
                 byline,
                 "{} and {}",
-                format_author(&authors[author_count - 2]),
-                format_author(&authors[author_count - 1])
+                format_author(&authors[author_count - 2], settings),
+                format_author(&authors[author_count - 1], settings)
             );
 
             byline
@@ -27,12 +27,16 @@ pub fn render_byline(authors: &[LegacyArticleAuthor]) -> String {
     }
 }
 
-pub fn format_author(author: &LegacyArticleAuthor) -> String {
+pub fn format_author(author: &LegacyArticleAuthor, settings: &Settings) -> String {
     match author
         .url
         .strip_prefix("https://www.reuters.com/journalists/")
     {
-        Some(path) => format!("<a href=\"/authors/{}/\">{}</a>", path, author.name),
+        Some(path) => format!(
+            "<a href=\"{}\">{}</a>",
+            settings.link(&format!("/authors/{path}/")),
+            author.name
+        ),
         None => format!("<a href=\"{}\">{}</a>", author.url, author.name),
     }
 }
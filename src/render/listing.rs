@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use maud::{html, Markup, PreEscaped};
+
+use crate::{
+    api::common::Article,
+    render::{byline::render_byline, images::render_thumbnail},
+    settings::{ListingStyle, Settings},
+};
+
+/* "5 minutes ago", falling back to the date for anything older than a week */
+fn relative_time(time: DateTime<Utc>) -> String {
+    let elapsed = Utc::now().signed_duration_since(time);
+    let plural = |count: i64, unit: &str| {
+        if count == 1 {
+            format!("1 {unit} ago")
+        } else {
+            format!("{count} {unit}s ago")
+        }
+    };
+
+    match elapsed.num_minutes() {
+        ..=0 => "just now".to_owned(),
+        minutes @ 1..=59 => plural(minutes, "minute"),
+        _ => match elapsed.num_hours() {
+            hours @ ..=23 => plural(hours, "hour"),
+            _ => match elapsed.num_days() {
+                days @ ..=6 => plural(days, "day"),
+                _ => time.format("%Y-%m-%d").to_string(),
+            },
+        },
+    }
+}
+
+fn render_card(article: &Article, terms: &[String], settings: &Settings) -> Markup {
+    let href = settings.link(&article.canonical_url);
+    let time = article.published_time.parse::<DateTime<Utc>>().ok();
+    let byline = article
+        .authors
+        .as_deref()
+        .map(|authors| render_byline(authors, settings))
+        .filter(|byline| !byline.is_empty());
+
+    html! {
+        article.card {
+            @if let Some(thumbnail) = &article.thumbnail {
+                (render_thumbnail(thumbnail, &href, settings))
+            }
+            h3 { a href=(href) { (highlight(&article.title, terms)) } }
+            p.byline {
+                @if let Some(time) = time {
+                    time datetime=(time.to_rfc3339()) title=(time.format("%Y-%m-%d %H:%M UTC")) {
+                        (relative_time(time))
+                    }
+                }
+                @if let Some(byline) = byline {
+                    @if time.is_some() {
+                        " - "
+                    }
+                    (PreEscaped(byline))
+                }
+            }
+            @if !article.description.is_empty() {
+                p { (highlight(&article.description, terms)) }
+            }
+        }
+    }
+}
+
+/* Splits text around case-insensitive matches of any term, preferring the longest */
+fn split_matches<'a>(text: &'a str, terms: &[String]) -> Vec<(&'a str, bool)> {
+    let mut parts = vec![];
+    let (mut start, mut index) = (0, 0);
+    while index < text.len() {
+        let matched = terms
+            .iter()
+            .filter(|term| {
+                text.get(index..index + term.len())
+                    .is_some_and(|part| part.eq_ignore_ascii_case(term))
+            })
+            .map(String::len)
+            .max();
+        match matched {
+            Some(len) => {
+                if start < index {
+                    parts.push((&text[start..index], false));
+                }
+                parts.push((&text[index..index + len], true));
+                index += len;
+                start = index;
+            }
+            None => index += text[index..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    if start < text.len() {
+        parts.push((&text[start..], false));
+    }
+    parts
+}
+
+fn highlight(text: &str, terms: &[String]) -> Markup {
+    if terms.is_empty() {
+        return html! { (text) };
+    }
+    html! {
+        @for (part, matched) in split_matches(text, terms) {
+            @if matched {
+                mark { (part) }
+            } @else {
+                (part)
+            }
+        }
+    }
+}
+
+/* Titles only in compact mode, cards with summary, time, byline and thumbnail otherwise */
+pub fn render_listing<'a>(
+    articles: impl IntoIterator<Item = &'a Article>,
+    settings: &Settings,
+) -> Markup {
+    render_highlighted_listing(articles, &[], settings)
+}
+
+/* Same as render_listing, marking the search terms in titles and descriptions */
+pub fn render_highlighted_listing<'a>(
+    articles: impl IntoIterator<Item = &'a Article>,
+    terms: &[String],
+    settings: &Settings,
+) -> Markup {
+    match settings.listing_style {
+        ListingStyle::Compact => html! {
+            ul {
+                @for article in articles {
+                    li { a href=(settings.link(&article.canonical_url)) { (highlight(&article.title, terms)) } }
+                }
+            }
+        },
+        ListingStyle::Cards => html! {
+            div.cards {
+                @for article in articles {
+                    (render_card(article, terms, settings))
+                }
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_around_matches() {
+        assert_eq!(
+            split_matches("Oil prices rise as oil demand grows", &terms(&["oil"])),
+            [
+                ("Oil", true),
+                (" prices rise as ", false),
+                ("oil", true),
+                (" demand grows", false),
+            ]
+        );
+        /* The longest term wins where several start */
+        assert_eq!(
+            split_matches("Oilfield", &terms(&["oil", "oilfield"])),
+            [("Oilfield", true)]
+        );
+        assert_eq!(
+            split_matches("Zürich oil", &terms(&["oil", "ich"])),
+            [("Zür", false), ("ich", true), (" ", false), ("oil", true)]
+        );
+        assert_eq!(split_matches("", &terms(&["oil"])), []);
+        assert_eq!(split_matches("gas", &terms(&["oil"])), [("gas", false)]);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod breadcrumbs;
 pub mod byline;
+pub mod follow;
 pub mod images;
 pub mod legacy_article_byline;
+pub mod listing;
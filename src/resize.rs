@@ -0,0 +1,346 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{assets::content_hash, render::images::RESIZE_STEPS, settings::ImagePlaceholders};
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageReader, Limits, RgbImage,
+};
+
+const PLACEHOLDER_WIDTH: u32 = 32;
+const PLACEHOLDER_QUALITY: u8 = 50;
+
+/* Set once at startup, pages only offer placeholders when images are processed */
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/* Upstream images are untrusted, a small file must not claim a huge canvas */
+const MAX_DIMENSION: u32 = 8192;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Jpeg,
+    Webp,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::Webp),
+            _ => Err(format!("Unknown image format: {s}")),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+pub struct Variant {
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+    pub etag: String,
+    pub last_modified: String,
+}
+
+/* Resized variants, evicted oldest first once the byte budget is exceeded */
+struct VariantCache {
+    entries: HashMap<String, Arc<Variant>>,
+    order: VecDeque<String>,
+    size: usize,
+    max_size: usize,
+}
+
+impl VariantCache {
+    fn insert(&mut self, key: String, variant: Arc<Variant>) {
+        if variant.data.len() > self.max_size {
+            return;
+        }
+        self.size += variant.data.len();
+        if let Some(old) = self.entries.insert(key.clone(), variant) {
+            self.size -= old.data.len();
+        } else {
+            self.order.push_back(key);
+        }
+        while self.size > self.max_size {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.size -= old.data.len();
+            }
+        }
+    }
+}
+
+pub fn init(enabled: bool) {
+    if ENABLED.set(enabled).is_err() {
+        panic!("Resizer already initialized");
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/* Processes and caches proxied images, resizing and placeholders are both opt-in */
+pub struct Resizer {
+    resize: bool,
+    format: OutputFormat,
+    quality: u8,
+    cache: Mutex<VariantCache>,
+}
+
+impl Resizer {
+    pub fn new(resize: bool, format: OutputFormat, quality: u8, cache_size: usize) -> Self {
+        Self {
+            resize,
+            format,
+            quality: quality.clamp(1, 100),
+            cache: Mutex::new(VariantCache {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                size: 0,
+                max_size: cache_size,
+            }),
+        }
+    }
+
+    pub fn resizes(&self) -> bool {
+        self.resize
+    }
+
+    /* WebP is only served to clients announcing support for it */
+    pub fn format_for(&self, accept: Option<&str>) -> OutputFormat {
+        match self.format {
+            OutputFormat::Webp if accept.is_some_and(|a| a.contains("image/webp")) => {
+                OutputFormat::Webp
+            }
+            _ => OutputFormat::Jpeg,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<Variant>> {
+        self.cache.lock().unwrap().entries.get(key).cloned()
+    }
+
+    pub fn process(
+        &self,
+        key: &str,
+        data: &[u8],
+        width: u32,
+        format: OutputFormat,
+    ) -> Option<Arc<Variant>> {
+        let image = decode(data)?;
+        let image = if image.width() > width {
+            image.resize(width, u32::MAX, FilterType::Triangle)
+        } else {
+            image
+        };
+
+        let data = encode(&image, format, self.quality)?;
+        Some(self.store(key, data, format))
+    }
+
+    /* Tiny blurred previews or dominant color boxes, keeping the aspect ratio */
+    pub fn placeholder(
+        &self,
+        key: &str,
+        data: &[u8],
+        kind: ImagePlaceholders,
+    ) -> Option<Arc<Variant>> {
+        let image = decode(data)?;
+        let image = match kind {
+            ImagePlaceholders::Off => return None,
+            ImagePlaceholders::Blur => image
+                .resize(PLACEHOLDER_WIDTH, u32::MAX, FilterType::Triangle)
+                .blur(1.0),
+            ImagePlaceholders::Color => {
+                let color = *image
+                    .resize_exact(1, 1, FilterType::Triangle)
+                    .to_rgb8()
+                    .get_pixel(0, 0);
+                let height = (PLACEHOLDER_WIDTH * image.height() / image.width().max(1)).max(1);
+                DynamicImage::ImageRgb8(RgbImage::from_pixel(PLACEHOLDER_WIDTH, height, color))
+            }
+        };
+
+        let data = encode(&image, OutputFormat::Jpeg, PLACEHOLDER_QUALITY)?;
+        Some(self.store(key, data, OutputFormat::Jpeg))
+    }
+
+    fn store(&self, key: &str, data: Vec<u8>, format: OutputFormat) -> Arc<Variant> {
+        let etag = content_hash(&data);
+        let variant = Arc::new(Variant {
+            content_type: format.content_type(),
+            etag: format!("\"{etag}\""),
+            last_modified: http_date(),
+            data,
+        });
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), variant.clone());
+        variant
+    }
+}
+
+fn decode(data: &[u8]) -> Option<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+fn encode(image: &DynamicImage, format: OutputFormat, quality: u8) -> Option<Vec<u8>> {
+    match format {
+        OutputFormat::Jpeg => {
+            let mut data = vec![];
+            let encoder = JpegEncoder::new_with_quality(&mut data, quality);
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(encoder)
+                .ok()?;
+            Some(data)
+        }
+        OutputFormat::Webp => {
+            let rgba = image.to_rgba8();
+            let data = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode(quality as f32)
+                .to_vec();
+            Some(data)
+        }
+    }
+}
+
+pub fn http_date() -> String {
+    chrono::Utc::now()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/* Splits a query parameter off a proxied path */
+pub fn take_param(path: &str, name: &str) -> (String, Option<String>) {
+    let Some((base, query)) = path.split_once('?') else {
+        return (path.to_owned(), None);
+    };
+    let mut value = None;
+    let rest: Vec<&str> = query
+        .split('&')
+        .filter(|pair| match pair.split_once('=') {
+            Some((key, v)) if key == name => {
+                value = Some(v.to_owned());
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    if rest.is_empty() {
+        (base.to_owned(), value)
+    } else {
+        (format!("{base}?{}", rest.join("&")), value)
+    }
+}
+
+/* Snap requested widths to the srcset steps so clients can't fill the cache with variants */
+pub fn requested_width(path: &str) -> Option<u32> {
+    let (_, query) = path.split_once('?')?;
+    let width = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("width="))?
+        .parse::<u32>()
+        .ok()?;
+    let step = RESIZE_STEPS
+        .iter()
+        .find(|step| u32::from(**step) >= width)
+        .or(RESIZE_STEPS.last())?;
+    Some(u32::from(*step))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::png::PngEncoder, ImageEncoder};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, image::Rgb([200, 100, 50]));
+        let mut data = vec![];
+        PngEncoder::new(&mut data)
+            .write_image(&image, width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn widths_snap_to_steps() {
+        assert_eq!(requested_width("resizer/a.jpg?width=1"), Some(480));
+        assert_eq!(requested_width("resizer/a.jpg?auth=x&width=700"), Some(720));
+        assert_eq!(requested_width("resizer/a.jpg?width=99999"), Some(1200));
+        assert_eq!(requested_width("resizer/a.jpg?width=abc"), None);
+        assert_eq!(requested_width("resizer/a.jpg"), None);
+    }
+
+    #[test]
+    fn params_are_split_off() {
+        assert_eq!(
+            take_param("a.jpg?width=480&auth=x", "width"),
+            ("a.jpg?auth=x".to_owned(), Some("480".to_owned()))
+        );
+        assert_eq!(
+            take_param("a.jpg?width=480", "width"),
+            ("a.jpg".to_owned(), Some("480".to_owned()))
+        );
+        assert_eq!(take_param("a.jpg", "width"), ("a.jpg".to_owned(), None));
+    }
+
+    #[test]
+    fn webp_needs_accept() {
+        let resizer = Resizer::new(true, OutputFormat::Webp, 80, 1024);
+        assert!(resizer.format_for(Some("image/avif,image/webp,*/*")) == OutputFormat::Webp);
+        assert!(resizer.format_for(Some("image/png,*/*")) == OutputFormat::Jpeg);
+        assert!(resizer.format_for(None) == OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn images_are_scaled_down() {
+        let resizer = Resizer::new(true, OutputFormat::Jpeg, 80, 1024 * 1024);
+        let variant = resizer
+            .process("key", &png(64, 32), 16, OutputFormat::Jpeg)
+            .unwrap();
+        let image = image::load_from_memory(&variant.data).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+        assert!(resizer.get("key").is_some());
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        assert!(decode(&png(MAX_DIMENSION + 1, 1)).is_none());
+        assert!(decode(b"not an image").is_none());
+    }
+
+    #[test]
+    fn cache_evicts_oldest() {
+        let resizer = Resizer::new(true, OutputFormat::Jpeg, 80, 10);
+        resizer.store("a", vec![0; 6], OutputFormat::Jpeg);
+        resizer.store("b", vec![0; 6], OutputFormat::Jpeg);
+        assert!(resizer.get("a").is_none());
+        assert!(resizer.get("b").is_some());
+    }
+}
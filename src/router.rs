@@ -0,0 +1,496 @@
+use std::sync::Arc;
+
+use neuters::{
+    api::{
+        error::{ApiError, ApiResult},
+        redirect::load_redirect,
+    },
+    client::Client,
+};
+use rouille::{Request, Response, ResponseBody};
+
+use crate::{
+    activitypub::Federation,
+    assets::serve_static,
+    front_page::FrontPage,
+    hierarchy::{Hierarchy, SectionMap},
+    pagination::{Page, SECTION_SIZES, TOPIC_SIZES},
+    render_api_error,
+    resize::Resizer,
+    routes::{
+        about::render_about,
+        activitypub::{handle_actor, webfinger},
+        article::render_article,
+        home::{render_home, render_my},
+        internet_news::render_legacy_article,
+        markets::render_market,
+        proxy::image_proxy,
+        resolve::handle_resolve,
+        search::{render_search, render_section, render_topic},
+        sections::render_sections,
+        settings::{handle_follow, handle_settings},
+    },
+    settings::Settings,
+    strip_prefix,
+};
+
+/* Everything the routes share across requests */
+pub struct App {
+    pub client: Arc<Client>,
+    pub hierarchy: Arc<Hierarchy>,
+    pub federation: Option<Arc<Federation>>,
+    pub resizer: Resizer,
+    pub public_url: Option<String>,
+    pub front_page: FrontPage,
+}
+
+pub struct Context<'a> {
+    pub app: &'a App,
+    pub request: &'a Request,
+    pub sections: &'a SectionMap,
+    /* Normalized path, without the settings token */
+    pub path: &'a str,
+    /* Remainder of the path after a prefix pattern */
+    pub rest: &'a str,
+    pub settings: &'a Settings,
+    pub token: Option<&'a str>,
+}
+
+type Handler = fn(&Context) -> ApiResult<Response>;
+
+enum Pattern {
+    Exact(&'static [&'static str]),
+    Prefix(&'static str),
+    /* Any section from the site hierarchy */
+    Section,
+    /* Everything else is treated as an article path */
+    Fallback,
+}
+
+const GET: &[&str] = &["GET"];
+const GET_POST: &[&str] = &["GET", "POST"];
+
+struct Route {
+    pattern: Pattern,
+    methods: &'static [&'static str],
+    handler: Handler,
+}
+
+/* Matched in order, the first route wins */
+const ROUTES: &[Route] = &[
+    Route {
+        pattern: Pattern::Exact(&["/", "/home"]),
+        methods: GET,
+        handler: home,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/my"]),
+        methods: GET,
+        handler: my,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/follow"]),
+        methods: &["POST"],
+        handler: follow,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/about"]),
+        methods: GET,
+        handler: about,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/settings"]),
+        methods: GET_POST,
+        handler: settings,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/sections"]),
+        methods: GET,
+        handler: sections,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/search"]),
+        methods: GET,
+        handler: search,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/favicon.ico", "/robots.txt"]),
+        methods: GET,
+        handler: static_file,
+    },
+    Route {
+        pattern: Pattern::Prefix("/static/"),
+        methods: GET,
+        handler: static_file,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/.well-known/webfinger"]),
+        methods: GET,
+        handler: well_known_webfinger,
+    },
+    Route {
+        pattern: Pattern::Prefix("/ap/actors/"),
+        methods: GET_POST,
+        handler: actor,
+    },
+    Route {
+        pattern: Pattern::Prefix("/proxy/"),
+        methods: GET,
+        handler: proxy,
+    },
+    Route {
+        pattern: Pattern::Exact(&["/go"]),
+        methods: GET,
+        handler: go,
+    },
+    Route {
+        pattern: Pattern::Prefix("/https:"),
+        methods: GET,
+        handler: pasted_link,
+    },
+    Route {
+        pattern: Pattern::Prefix("/http:"),
+        methods: GET,
+        handler: pasted_link,
+    },
+    Route {
+        pattern: Pattern::Prefix("/www.reuters.com/"),
+        methods: GET,
+        handler: pasted_link,
+    },
+    Route {
+        pattern: Pattern::Prefix("/reuters.com/"),
+        methods: GET,
+        handler: pasted_link,
+    },
+    Route {
+        pattern: Pattern::Section,
+        methods: GET,
+        handler: section,
+    },
+    Route {
+        pattern: Pattern::Prefix("/authors/"),
+        methods: GET,
+        handler: author,
+    },
+    Route {
+        pattern: Pattern::Prefix("/topic/"),
+        methods: GET,
+        handler: topic,
+    },
+    Route {
+        pattern: Pattern::Prefix("/article/"),
+        methods: GET,
+        handler: legacy_article,
+    },
+    Route {
+        pattern: Pattern::Prefix("/companies/"),
+        methods: GET,
+        handler: market,
+    },
+    Route {
+        pattern: Pattern::Prefix("/markets/companies/"),
+        methods: GET,
+        handler: market,
+    },
+    Route {
+        pattern: Pattern::Fallback,
+        methods: GET,
+        handler: article,
+    },
+];
+
+impl Pattern {
+    /* Returns the remainder of the path on a match */
+    fn matches<'a>(&self, sections: &SectionMap, path: &'a str) -> Option<&'a str> {
+        match self {
+            Self::Exact(paths) => paths.contains(&path).then_some(""),
+            Self::Prefix(prefix) => path.strip_prefix(prefix),
+            Self::Section => sections.contains_key(path).then_some(path),
+            Self::Fallback => Some(path),
+        }
+    }
+
+    /* Only pages with a fixed set of paths have a canonical form */
+    fn is_canonical(&self) -> bool {
+        matches!(self, Self::Exact(_) | Self::Section)
+    }
+}
+
+/* Sections end in a slash, pages don't, other paths are passed on untouched */
+fn canonical_path(sections: &SectionMap, path: &str) -> Option<String> {
+    let alternative = match path.strip_suffix('/') {
+        Some("") => return None,
+        Some(trimmed) => trimmed.to_owned(),
+        None => format!("{path}/"),
+    };
+    ROUTES
+        .iter()
+        .filter(|route| route.pattern.is_canonical())
+        .any(|route| route.pattern.matches(sections, &alternative).is_some())
+        .then_some(alternative)
+}
+
+fn find_route<'a>(sections: &SectionMap, path: &'a str) -> Option<(&'static Route, &'a str)> {
+    ROUTES
+        .iter()
+        .find_map(|route| Some((route, route.pattern.matches(sections, path)?)))
+}
+
+pub fn dispatch(
+    app: &App,
+    request: &Request,
+    settings: &Settings,
+    token: Option<&str>,
+) -> Response {
+    let path = request.url();
+    let is_head = request.method() == "HEAD";
+    let method = if is_head { "GET" } else { request.method() };
+
+    let sections = app.hierarchy.sections();
+
+    let Some((route, rest)) = find_route(&sections, &path) else {
+        return render_api_error(&ApiError::Empty, &path, settings);
+    };
+
+    if matches!(route.pattern, Pattern::Fallback) {
+        if let Some(canonical) = canonical_path(&sections, &path) {
+            return redirect_canonical(request, &canonical);
+        }
+    }
+
+    if !route.methods.contains(&method) {
+        return Response::text("Method not allowed")
+            .with_status_code(405)
+            .with_unique_header("Allow", allow_header(route.methods));
+    }
+
+    let context = Context {
+        app,
+        request,
+        sections: &sections,
+        path: &path,
+        rest,
+        settings,
+        token,
+    };
+
+    let mut response = match (route.handler)(&context) {
+        Ok(response) => response,
+        Err(err) => render_api_error(&err, &path, settings),
+    };
+
+    if is_head {
+        response.data = ResponseBody::empty();
+    }
+    response
+}
+
+fn allow_header(methods: &[&str]) -> String {
+    let mut allow = methods.to_vec();
+    if allow.contains(&"GET") {
+        allow.push("HEAD");
+    }
+    allow.join(", ")
+}
+
+/* Permanent, keeping the query string */
+fn redirect_canonical(request: &Request, path: &str) -> Response {
+    let location = match request.raw_query_string() {
+        "" => path.to_owned(),
+        query => format!("{path}?{query}"),
+    };
+    Response::redirect_301(location)
+}
+
+fn home(ctx: &Context) -> ApiResult<Response> {
+    render_home(
+        &ctx.app.client,
+        ctx.sections,
+        &ctx.app.front_page,
+        ctx.settings,
+    )
+    .map(Response::html)
+}
+
+fn my(ctx: &Context) -> ApiResult<Response> {
+    render_my(
+        &ctx.app.client,
+        ctx.sections,
+        &ctx.app.front_page,
+        ctx.settings,
+    )
+    .map(Response::html)
+}
+
+fn follow(ctx: &Context) -> ApiResult<Response> {
+    Ok(handle_follow(
+        ctx.request,
+        ctx.sections,
+        ctx.settings,
+        ctx.token,
+    ))
+}
+
+fn about(ctx: &Context) -> ApiResult<Response> {
+    render_about(ctx.settings).map(Response::html)
+}
+
+fn settings(ctx: &Context) -> ApiResult<Response> {
+    Ok(handle_settings(ctx.request, ctx.settings, ctx.token))
+}
+
+fn sections(ctx: &Context) -> ApiResult<Response> {
+    render_sections(ctx.sections, ctx.settings).map(Response::html)
+}
+
+fn search(ctx: &Context) -> ApiResult<Response> {
+    render_search(&ctx.app.client, ctx.sections, ctx.request, ctx.settings)
+}
+
+fn static_file(ctx: &Context) -> ApiResult<Response> {
+    serve_static(ctx.request, ctx.path, ctx.app.public_url.as_deref()).ok_or(ApiError::Empty)
+}
+
+fn well_known_webfinger(ctx: &Context) -> ApiResult<Response> {
+    let federation = ctx.app.federation.as_ref().ok_or(ApiError::Empty)?;
+    Ok(webfinger(federation, ctx.sections, ctx.request))
+}
+
+fn actor(ctx: &Context) -> ApiResult<Response> {
+    let federation = ctx.app.federation.as_ref().ok_or(ApiError::Empty)?;
+    Ok(handle_actor(
+        federation,
+        &ctx.app.client,
+        ctx.sections,
+        ctx.request,
+        ctx.rest,
+    ))
+}
+
+/* The proxied path keeps its query string */
+fn proxy(ctx: &Context) -> ApiResult<Response> {
+    let raw_url = ctx.request.raw_url();
+    let path = raw_url.strip_prefix("/proxy/").ok_or(ApiError::Empty)?;
+    Ok(image_proxy(
+        &ctx.app.client,
+        &ctx.app.resizer,
+        ctx.request,
+        path,
+    ))
+}
+
+fn go(ctx: &Context) -> ApiResult<Response> {
+    match ctx.request.get_param("url") {
+        Some(url) => handle_resolve(&ctx.app.client, &url),
+        None => Ok(Response::text("Missing url").with_status_code(400)),
+    }
+}
+
+/* The raw url keeps the query string of the pasted link */
+fn pasted_link(ctx: &Context) -> ApiResult<Response> {
+    let raw_url = ctx.request.raw_url();
+    handle_resolve(&ctx.app.client, &raw_url[1..])
+}
+
+fn section(ctx: &Context) -> ApiResult<Response> {
+    let section = ctx.sections.get(ctx.path).ok_or(ApiError::Empty)?;
+    render_section(
+        &ctx.app.client,
+        ctx.sections,
+        section,
+        &Page::from_request(ctx.request, &SECTION_SIZES),
+        ctx.settings,
+    )
+    .map(Response::html)
+}
+
+fn author(ctx: &Context) -> ApiResult<Response> {
+    render_topic(
+        &ctx.app.client,
+        ctx.path,
+        &Page::from_request(ctx.request, &TOPIC_SIZES),
+        ctx.settings,
+    )
+    .map(Response::html)
+}
+
+fn topic(ctx: &Context) -> ApiResult<Response> {
+    let full_path = format!("https://www.reuters.com/topic/{}", ctx.rest);
+    let (status, location) = load_redirect(&ctx.app.client, &full_path)?;
+    Ok(Response {
+        status_code: status,
+        headers: vec![
+            ("Location".into(), strip_prefix(&location).to_owned().into()),
+            ("Cache-Control".into(), "public, max-age=31536000".into()),
+        ],
+        data: ResponseBody::empty(),
+        upgrade: None,
+    })
+}
+
+fn legacy_article(ctx: &Context) -> ApiResult<Response> {
+    match render_legacy_article(&ctx.app.client, ctx.path, ctx.settings) {
+        Ok(result) => result.map(Response::html),
+        Err(response) => Ok(response),
+    }
+}
+
+fn market(ctx: &Context) -> ApiResult<Response> {
+    render_market(&ctx.app.client, ctx.rest, ctx.settings).map(Response::html)
+}
+
+fn article(ctx: &Context) -> ApiResult<Response> {
+    render_article(&ctx.app.client, ctx.sections, ctx.path, ctx.settings).map(Response::html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sections;
+
+    #[test]
+    fn routes() {
+        let sections = sections();
+        let route = |path| find_route(&sections, path).map(|(route, rest)| (&route.pattern, rest));
+
+        assert!(matches!(route("/"), Some((Pattern::Exact(_), ""))));
+        assert!(matches!(route("/about"), Some((Pattern::Exact(_), ""))));
+        assert!(matches!(
+            route("/world/europe/"),
+            Some((Pattern::Section, "/world/europe/"))
+        ));
+        assert!(matches!(
+            route("/authors/jane-doe/"),
+            Some((Pattern::Prefix("/authors/"), "jane-doe/"))
+        ));
+        assert!(matches!(
+            route("/world/europe/some-story-2024-01-01/"),
+            Some((Pattern::Fallback, _))
+        ));
+    }
+
+    #[test]
+    fn canonical_paths() {
+        let sections = sections();
+        assert_eq!(
+            canonical_path(&sections, "/about/"),
+            Some("/about".to_owned())
+        );
+        assert_eq!(
+            canonical_path(&sections, "/world"),
+            Some("/world/".to_owned())
+        );
+        assert_eq!(canonical_path(&sections, "/"), None);
+        assert_eq!(
+            canonical_path(&sections, "/world/some-story-2024-01-01/"),
+            None
+        );
+    }
+
+    #[test]
+    fn allowed_methods() {
+        assert_eq!(allow_header(GET_POST), "GET, POST, HEAD");
+        assert_eq!(allow_header(&["POST"]), "POST");
+    }
+}
@@ -1,12 +1,14 @@
 use crate::api::error::ApiResult;
 use crate::document;
+use crate::settings::Settings;
 use maud::html;
 use std::env;
 
 const GIT_HASH: &str = env!("GIT_HASH");
 
-pub fn render_about() -> ApiResult<String> {
+pub fn render_about(settings: &Settings) -> ApiResult<String> {
     let doc = document!(
+        settings,
         "About",
         html! {
             h1 { "About" }
@@ -16,7 +18,7 @@ pub fn render_about() -> ApiResult<String> {
                 li { "No tracking" }
                 li { "No cookies" }
                 li { "Lightweight (usually <10KiB vs 50MiB from Reuters)" }
-                li { "Dynamic Theming (respects system theme)" }
+                li { "Dynamic Theming (respects system theme, or pick a theme and typography in the settings)" }
             }
             p { "You can install " a href="https://libredirect.github.io/" { "libredirect" } " or " a href="https://addons.mozilla.org/en-US/firefox/addon/reuters-redirect/" { "this browser extension" } " to automatically forwards all reuters links to this site." }
             p { "This is a work in progress. Please report any bugs or suggestions at " a href="https://github.com/HookedBehemoth/neuters" { "GitHub" } "." }
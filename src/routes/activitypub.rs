@@ -0,0 +1,236 @@
+use std::{collections::HashMap, io::Read, sync::Arc, thread};
+
+use rouille::{Request, Response};
+use serde_json::{json, Value};
+
+use crate::{
+    activitypub::{
+        fetch_latest, is_author_handle, objects, path_for_handle,
+        signature::{signed_post, verify_request},
+        Federation, Follower, ACTIVITY_JSON,
+    },
+    api::topic::fetch_articles_by_topic,
+    client::Client,
+    Section,
+};
+
+const MAX_INBOX_BODY: u64 = 1024 * 1024;
+const OUTBOX_SIZE: u32 = 20;
+
+fn activity_response(value: &Value) -> Response {
+    Response::from_data(ACTIVITY_JSON, value.to_string())
+}
+
+fn actor_name(
+    client: &Client,
+    sections_by_id: &HashMap<String, Section>,
+    handle: &str,
+) -> Option<String> {
+    let path = path_for_handle(handle);
+    if is_author_handle(handle) {
+        let articles = fetch_articles_by_topic(client, &path, 0, 1).ok()?;
+        articles
+            .topics
+            .and_then(|topics| topics.first().map(|t| t.name.clone()))
+    } else {
+        sections_by_id.get(&path).map(|s| s.name.clone())
+    }
+}
+
+pub fn webfinger(
+    fed: &Federation,
+    sections_by_id: &HashMap<String, Section>,
+    request: &Request,
+) -> Response {
+    let Some(resource) = request.get_param("resource") else {
+        return Response::text("Missing resource").with_status_code(400);
+    };
+
+    let handle = if let Some(account) = resource.strip_prefix("acct:") {
+        match account.split_once('@') {
+            Some((handle, host)) if host == fed.host => handle.to_owned(),
+            _ => return Response::empty_404(),
+        }
+    } else if let Some(handle) = resource.strip_prefix(&fed.actor_id("")) {
+        handle.to_owned()
+    } else {
+        return Response::empty_404();
+    };
+
+    /* Author actors are resolved lazily, sections have to exist */
+    let path = path_for_handle(&handle);
+    if !is_author_handle(&handle) && !sections_by_id.contains_key(&path) {
+        return Response::empty_404();
+    }
+
+    let body = json!({
+        "subject": format!("acct:{handle}@{}", fed.host),
+        "aliases": [fed.actor_id(&handle)],
+        "links": [
+            {
+                "rel": "self",
+                "type": ACTIVITY_JSON,
+                "href": fed.actor_id(&handle),
+            },
+            {
+                "rel": "http://webfinger.net/rel/profile-page",
+                "type": "text/html",
+                "href": format!("{}{path}", fed.base_url),
+            },
+        ],
+    });
+
+    Response::from_data("application/jrd+json", body.to_string())
+}
+
+/* Everything below /ap/actors/ */
+pub fn handle_actor(
+    fed: &Arc<Federation>,
+    client: &Client,
+    sections_by_id: &HashMap<String, Section>,
+    request: &Request,
+    path: &str,
+) -> Response {
+    let (handle, endpoint) = path.split_once('/').unwrap_or((path, ""));
+
+    /* Unknown handles can't be followed either */
+    let Some(name) = actor_name(client, sections_by_id, handle) else {
+        return Response::empty_404();
+    };
+
+    if request.method() == "POST" {
+        return match endpoint {
+            "inbox" => inbox(fed, request, handle),
+            _ => Response::text("Method not allowed").with_status_code(405),
+        };
+    }
+
+    match endpoint {
+        "" => activity_response(&objects::actor(
+            fed,
+            handle,
+            &name,
+            &path_for_handle(handle),
+        )),
+        "outbox" => match fetch_latest(client, handle, OUTBOX_SIZE) {
+            Ok(articles) => activity_response(&objects::outbox(
+                fed,
+                handle,
+                &articles.articles.unwrap_or_default(),
+            )),
+            Err(err) => {
+                Response::text(format!("Failed to load outbox: {err:?}")).with_status_code(502)
+            }
+        },
+        "followers" => activity_response(&objects::followers(fed, handle)),
+        _ => Response::empty_404(),
+    }
+}
+
+/* Where deliveries to a verified actor go, never to a host other than the actor's own */
+fn follower_inbox<'a>(
+    host_check: fn(&str) -> Result<&str, String>,
+    actor: &str,
+    signer: &'a Value,
+) -> Result<&'a str, &'static str> {
+    let actor_host = host_check(actor).ok();
+    let on_actor_host = |url: &str| host_check(url).ok() == actor_host;
+    let own_inbox = signer["inbox"].as_str();
+    let shared_inbox = signer["endpoints"]["sharedInbox"].as_str();
+    if !own_inbox.into_iter().chain(shared_inbox).all(on_actor_host) {
+        return Err("Inbox not on the actor's host");
+    }
+    shared_inbox.or(own_inbox).ok_or("Actor has no inbox")
+}
+
+fn inbox(fed: &Arc<Federation>, request: &Request, handle: &str) -> Response {
+    let mut body = vec![];
+    let read = request
+        .data()
+        .map(|data| data.take(MAX_INBOX_BODY).read_to_end(&mut body));
+    if !matches!(read, Some(Ok(_))) {
+        return Response::text("Failed to read body").with_status_code(400);
+    }
+
+    let Ok(activity) = serde_json::from_slice::<Value>(&body) else {
+        return Response::text("Invalid activity").with_status_code(400);
+    };
+    let Some(actor) = activity["actor"].as_str() else {
+        return Response::text("Invalid actor").with_status_code(400);
+    };
+
+    let signer = match verify_request(fed, handle, request, &body, actor) {
+        Ok(signer) => signer,
+        Err(err) => return Response::text(err).with_status_code(401),
+    };
+
+    match activity["type"].as_str() {
+        Some("Follow") if activity["object"].as_str() == Some(&fed.actor_id(handle)) => {
+            let inbox = match follower_inbox(fed.host_check, actor, &signer) {
+                Ok(inbox) => inbox,
+                Err(err) => return Response::text(err).with_status_code(400),
+            };
+
+            fed.add_follower(
+                handle,
+                Follower {
+                    actor: actor.to_owned(),
+                    inbox: inbox.to_owned(),
+                },
+            );
+
+            /* Send the Accept to the actor's own inbox, outside of the request */
+            let fed = fed.clone();
+            let handle = handle.to_owned();
+            let inbox = signer["inbox"].as_str().unwrap_or(inbox).to_owned();
+            thread::spawn(move || {
+                let accept = objects::accept(&fed, &handle, &activity).to_string();
+                if let Err(err) = signed_post(&fed, &handle, &inbox, accept.as_bytes()) {
+                    eprintln!("Failed to accept follow from {inbox}: {err}");
+                }
+            });
+        }
+        Some("Undo") if activity["object"]["type"].as_str() == Some("Follow") => {
+            fed.remove_follower(handle, actor);
+        }
+        _ => {}
+    }
+
+    Response::text("").with_status_code(202)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::activitypub::signature::remote_host;
+
+    use super::*;
+
+    const ACTOR: &str = "https://remote.example/users/alice";
+
+    #[test]
+    fn inboxes_stay_on_the_actor_host() {
+        let inbox = |signer: Value| follower_inbox(remote_host, ACTOR, &signer).map(str::to_owned);
+        assert_eq!(
+            inbox(json!({
+                "inbox": "https://remote.example/users/alice/inbox",
+                "endpoints": {"sharedInbox": "https://remote.example/inbox"}
+            })),
+            Ok("https://remote.example/inbox".to_owned())
+        );
+        assert_eq!(
+            inbox(json!({"inbox": "https://remote.example/users/alice/inbox"})),
+            Ok("https://remote.example/users/alice/inbox".to_owned())
+        );
+        for signer in [
+            json!({"inbox": "https://other.example/inbox"}),
+            json!({"inbox": "https://127.0.0.1/inbox"}),
+            json!({
+                "inbox": "https://remote.example/users/alice/inbox",
+                "endpoints": {"sharedInbox": "http://localhost:8080/inbox"}
+            }),
+            json!({}),
+        ] {
+            assert!(inbox(signer.clone()).is_err(), "{signer}");
+        }
+    }
+}
@@ -1,14 +1,28 @@
 use crate::{
     api::{article::fetch_article_by_url, error::ApiResult},
     client::Client,
-    render::{byline, images::render_image},
+    hierarchy::SectionMap,
+    local_index,
+    render::{
+        breadcrumbs::{article_trail, render_breadcrumbs},
+        byline,
+        images::{image_from_element, render_image},
+    },
     settings::Settings,
 };
 use chrono::{DateTime, Utc};
 use maud::{html, PreEscaped};
 
-pub fn render_article(client: &Client, path: &str, settings: &Settings) -> ApiResult<String> {
+pub fn render_article(
+    client: &Client,
+    sections: &SectionMap,
+    path: &str,
+    settings: &Settings,
+) -> ApiResult<String> {
     let article = fetch_article_by_url(client, path)?;
+    if article.subtype.as_deref() != Some("live-blog") {
+        local_index::record(&article);
+    }
 
     let published_time = article
         .published_time
@@ -16,12 +30,14 @@ pub fn render_article(client: &Client, path: &str, settings: &Settings) -> ApiRe
         .map(|time| time.format("%Y-%m-%d %H:%M").to_string());
 
     let doc = crate::document!(
+        settings,
         &article.title,
         html!(
+            (render_breadcrumbs(&article_trail(sections, &article.canonical_url), settings))
             h1 { (&article.title) }
             p class="byline" {
                 @if let Some(authors) = &article.authors {
-                    @let byline = byline::render_byline(authors);
+                    @let byline = byline::render_byline(authors, settings);
                     @if let Ok(time) = published_time {
                         (time) " - "
                     }
@@ -78,57 +94,27 @@ fn render_items(items: &[serde_json::Value], settings: &Settings) -> maud::Marku
                 Some("paragraph") => {
                     @if let Some(content) = content["content"].as_str() {
                         @if content.contains("href=\"https://www.reuters.com/") {
-                            p { (PreEscaped(content.replace("href=\"https://www.reuters.com/", "href=\"/"))) }
+                            @let local = format!("href=\"{}", settings.link("/"));
+                            p { (PreEscaped(content.replace("href=\"https://www.reuters.com/", &local))) }
                         } @else {
                             p { (PreEscaped(content)) }
                         };
                     }
                 }
                 Some("image") => {
-                    @if settings.embed_images {
-                        @if let Some(image) = content["url"].as_str() {
-                            @let alt = content["alt"].as_str();
-                            @let (width, height) = (content["width"].as_u64(), content["height"].as_u64());
-                            img src=(image) alt=[alt] width=[width] height=[height];
-                        }
-                    } @else {
-                        p {
-                            i {
-                                "Embedding images is disabled. Navigate to the original resource or change the settings to enable it."
-                            }
-                        }
-                        @if let Some(image) = content["url"].as_str() {
-                            p {
-                                a href=(image) { "Image" }
-                            }
-                        }
+                    @if let Some(image) = image_from_element(content) {
+                        (render_image(&image, settings))
                     }
                 }
                 Some("graphic") => {
-                    @if settings.embed_images {
-                        @match content["graphic_type"].as_str() {
-                            Some("image") => {
-                                @if let (Some(image), Some(description)) = (content["url"].as_str(), content["description"].as_str()) {
-                                    figure {
-                                        img src=(image) alt=(description);
-                                        figcaption { (description) }
-                                    }
-                                }
-                            }
-                            Some(unknown) => { p { "Unknown graphic type: " (unknown) } }
-                            None => { p { "Missing graphic type" } }
-                        }
-                    } @else {
-                        p {
-                            i {
-                                "Embedding images is disabled. Navigate to the original resource or change the settings to enable it."
-                            }
-                        }
-                        @if let Some(image) = content["url"].as_str() {
-                            p {
-                                a href=(image) { "Image" }
+                    @match content["graphic_type"].as_str() {
+                        Some("image") => {
+                            @if let Some(image) = image_from_element(content) {
+                                (render_image(&image, settings))
                             }
                         }
+                        Some(unknown) => { p { "Unknown graphic type: " (unknown) } }
+                        None => { p { "Missing graphic type" } }
                     }
                 }
                 Some("table") => {
@@ -0,0 +1,134 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use maud::{html, Markup};
+
+use crate::{
+    api::{common::Article, error::ApiResult},
+    client::Client,
+    document,
+    front_page::{dedupe, FrontPage},
+    hierarchy::{SectionMap, ROOT_SECTION},
+    render::listing::render_listing,
+    settings::{is_followable, Follows, Settings},
+    Section,
+};
+
+const MY_PAGE_SIZE: usize = 30;
+
+/* Listings /my fetches per request, one round of fetches. The rest has to come from the
+ * listing cache, long follow lists fill it over a few visits. */
+const MY_FETCHES: usize = 8;
+
+fn render_intro(sections_by_id: &HashMap<String, Section>, settings: &Settings) -> Markup {
+    let top_level = sections_by_id
+        .get(ROOT_SECTION)
+        .map(|root| root.children.as_slice())
+        .unwrap_or_default();
+
+    html! {
+        h1 { "Neuters" }
+        form.paste action=(settings.link("/go")) {
+            input type="text" name="url" placeholder="Paste a Reuters link..." required="";
+            button type="submit" { "Open" }
+        }
+        @if !top_level.is_empty() {
+            nav.sections {
+                @for section in top_level {
+                    a href=(settings.link(&section.id)) { (section.name) }
+                }
+            }
+        }
+    }
+}
+
+/* Landing page, followed by the latest stories of the operator's front page sections */
+pub fn render_home(
+    client: &Client,
+    sections_by_id: &HashMap<String, Section>,
+    front_page: &FrontPage,
+    settings: &Settings,
+) -> ApiResult<String> {
+    let blocks = front_page.blocks(client);
+    let listings = dedupe(&blocks, front_page.size());
+
+    let doc = document!(
+        settings,
+        "Neuters - Reuters Proxy",
+        html! {
+            (render_intro(sections_by_id, settings))
+            @for (block, articles) in blocks.iter().zip(listings) {
+                @if !articles.is_empty() {
+                    @let name = sections_by_id
+                        .get(&block.path)
+                        .map_or(block.path.as_str(), |section| section.name.as_str());
+                    h2 { a href=(settings.link(&block.path)) { (name) } }
+                    (render_listing(articles, settings))
+                }
+            }
+            @if blocks.is_empty() {
+                p { i { "Latest stories are currently unavailable." } }
+            }
+        },
+    );
+
+    Ok(doc.into_string())
+}
+
+/* Latest stories of everything the reader follows, newest first */
+pub fn render_my(
+    client: &Client,
+    sections: &SectionMap,
+    front_page: &FrontPage,
+    settings: &Settings,
+) -> ApiResult<String> {
+    let follows = settings.follows.paths();
+    /* Sections removed from the hierarchy since they were followed are skipped */
+    let listings: Vec<String> = follows
+        .iter()
+        .filter(|path| is_followable(sections, path))
+        .cloned()
+        .collect();
+    let (blocks, pending) = front_page.cached_listings(client, &listings, MY_FETCHES);
+
+    let mut seen = HashSet::new();
+    let mut dated: Vec<(DateTime<Utc>, &Article)> = blocks
+        .iter()
+        .flat_map(|block| block.articles.articles.as_deref().unwrap_or_default())
+        .filter(|article| seen.insert(article.canonical_url.as_str()))
+        .filter_map(|article| Some((article.published_time.parse().ok()?, article)))
+        .collect();
+    dated.sort_by(|(a, _), (b, _)| b.cmp(a));
+    dated.truncate(MY_PAGE_SIZE);
+    let articles: Vec<&Article> = dated.into_iter().map(|(_, article)| article).collect();
+
+    let doc = document!(
+        settings,
+        "Neuters - Followed",
+        html! {
+            h1 { "Followed" }
+            @if follows.is_empty() {
+                p {
+                    "Follow sections, topics and authors from their pages to collect their latest stories here. Start with the "
+                    a href=(settings.link("/sections")) { "sections" }
+                    "."
+                }
+            } @else if articles.is_empty() {
+                p { i { "Latest stories are currently unavailable." } }
+            } @else {
+                (render_listing(articles, settings))
+            }
+            @if pending > 0 {
+                p { i { "Some followed listings haven't loaded yet, reload to see their stories." } }
+            }
+            p {
+                "Following " (follows.len()) " of at most " (Follows::MAX) ". Edit the list in the "
+                a href=(settings.link("/settings")) { "settings" }
+                "."
+            }
+        },
+    );
+
+    Ok(doc.into_string())
+}
@@ -3,21 +3,24 @@ use maud::{html, PreEscaped};
 
 use crate::{
     api::{
+        common::Image,
         error::{ApiError, ApiResult},
         legacy_article::{fetch_legacy_article, parse_legacy_article},
     },
     client::Client,
-    render::legacy_article_byline::render_byline,
+    render::{images::render_image, legacy_article_byline::render_byline},
+    settings::Settings,
 };
 
 pub fn render_legacy_article(
     client: &Client,
     path: &str,
+    settings: &Settings,
 ) -> Result<ApiResult<String>, rouille::Response> {
     let response = match fetch_legacy_article(client, path) {
         Ok(response) => response,
         Err(err) => {
-            return Ok(Err(ApiError::from(err)));
+            return Ok(Err(ApiError::from(*err)));
         }
     };
 
@@ -57,17 +60,36 @@ pub fn render_legacy_article(
         .parse::<DateTime<Utc>>()
         .map(|time| time.format("%Y-%m-%d %H:%M").to_string());
 
+    let lead_image = article
+        .images
+        .as_deref()
+        .unwrap_or_default()
+        .first()
+        .map(|image| Image {
+            url: Some(image.url.clone()),
+            caption: image.caption.clone(),
+            width: image.width,
+            height: image.height,
+            ..Default::default()
+        });
+
     let doc = crate::document!(
+        settings,
         &article.headline,
         html! {
             h1 { (&article.headline) }
             p class="byline" {
-                @let byline = render_byline(&article.authors);
+                @let byline = render_byline(&article.authors, settings);
                 @if let Ok(time) = published_time {
                     (time) " - "
                 }
                 (PreEscaped(byline))
             }
+            @if settings.embed_images {
+                @if let Some(image) = &lead_image {
+                    (render_image(image, settings))
+                }
+            }
             @for content in article.body_items.iter() {
                 @match content.r#type.as_str() {
                     "paragraph" => {
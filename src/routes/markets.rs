@@ -1,9 +1,10 @@
 use crate::{
     api::{error::ApiResult, markit::fetch_by_stock_symbol},
     client::Client,
+    settings::Settings,
 };
 
-pub fn render_market(client: &Client, path: &str) -> ApiResult<String> {
+pub fn render_market(client: &Client, path: &str, settings: &Settings) -> ApiResult<String> {
     let company = if let Some(end) = path.find('/') {
         &path[..end]
     } else {
@@ -13,12 +14,13 @@ pub fn render_market(client: &Client, path: &str) -> ApiResult<String> {
     let articles = fetch_by_stock_symbol(client, company)?;
 
     let document = crate::document! {
+        settings,
         company,
         maud::html! {
             company
             ul {
                 @for article in articles.articles.iter() {
-                    li { a href=(&article.canonical_url) { (&article.title) } }
+                    li { a href=(settings.link(&article.canonical_url)) { (&article.title) } }
                 }
             }
         },
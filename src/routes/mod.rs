@@ -1,7 +1,11 @@
 pub mod about;
+pub mod activitypub;
 pub mod article;
+pub mod home;
 pub mod internet_news;
 pub mod markets;
 pub mod proxy;
+pub mod resolve;
 pub mod search;
+pub mod sections;
 pub mod settings;
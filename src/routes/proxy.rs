@@ -1,49 +1,336 @@
+use std::{borrow::Cow, io::Read, ops::Range};
+
+use chrono::DateTime;
 use rouille::{Request, Response};
 
-use crate::client::Client;
+use crate::{
+    client::Client,
+    resize::{requested_width, take_param, Resizer, Variant},
+    settings::ImagePlaceholders,
+};
 
 const SITE_PREFIX: &str = "https://www.reuters.com/";
 
-/* Note: passing these to the client should be avoided */
-const FORBIDDEN_CLIENT_HEADERS: &[&str] = &["connection", "cookies", "set-cookie"];
-const FORBIDDEN_SERVER_HEADERS: &[&str] =
-    &["connection", "cookie", "user-agent", "host", "referer"];
+/* Only Reuters' resizer and arc image storage are reachable through the proxy */
+const ALLOWED_PATHS: &[&str] = &["resizer/", "arc/"];
+const VIDEO_PATHS: &[&str] = &["arc/video/"];
+
+/* Note: everything not listed here is dropped, this keeps cookies and tracking out */
+const FORWARDED_CLIENT_HEADERS: &[&str] = &[
+    "accept",
+    "if-none-match",
+    "if-modified-since",
+    "range",
+    "if-range",
+];
+/* Processed images are answered from the cache, upstream only sees what it may encode */
+const PROCESSED_CLIENT_HEADERS: &[&str] = &["accept"];
+const FORWARDED_SERVER_HEADERS: &[&str] = &[
+    "content-type",
+    "cache-control",
+    "etag",
+    "last-modified",
+    "expires",
+    "accept-ranges",
+    "content-range",
+];
+
+/* Resizer urls are signed and never change their content */
+const IMMUTABLE_PATH: &str = "resizer/";
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/* Width requested from the resizer when only a placeholder is needed */
+const PLACEHOLDER_SOURCE_WIDTH: u32 = 480;
 
 pub fn strip_prefix(path: &str) -> Option<&str> {
     path.strip_prefix(SITE_PREFIX)
 }
 
-pub fn image_proxy(
+/* The path is still percent encoded, so encoded dots are rejected along with plain ones */
+pub fn is_allowed_path(path: &str) -> bool {
+    ALLOWED_PATHS.iter().any(|prefix| path.starts_with(prefix))
+        && !path.contains("..")
+        && !path.to_ascii_lowercase().contains("%2e")
+}
+
+/* Only images, SVG excluded as it can carry scripts that would run on this origin.
+ * Videos need range requests and are only passed through from their own paths. */
+fn is_allowed_type(path: &str, content_type: &str) -> bool {
+    if content_type.starts_with("video/") {
+        return VIDEO_PATHS.iter().any(|prefix| path.starts_with(prefix));
+    }
+    content_type.starts_with("image/") && !content_type.starts_with("image/svg")
+}
+
+/* Only single ranges are supported, anything else is answered with the full body */
+fn parse_range(header: &str, len: usize) -> Option<Range<usize>> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let range = match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(start), Ok(end)) => start..end.saturating_add(1).min(len),
+        (Ok(start), Err(_)) if end.is_empty() => start..len,
+        (Err(_), Ok(suffix)) if start.is_empty() => len.saturating_sub(suffix)..len,
+        _ => return None,
+    };
+    (range.start < range.end).then_some(range)
+}
+
+/* Open or oversized ranges are cut down to the size limit, so players stream long videos
+ * in pieces instead of having them rejected. Anything unparsed is forwarded as it is. */
+fn capped_range(header: &str) -> Option<String> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let max = MAX_BODY_SIZE as u64;
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if end >= start => Some(format!(
+            "bytes={start}-{}",
+            end.min(start.saturating_add(max - 1))
+        )),
+        (Ok(start), Err(_)) if end.is_empty() => {
+            Some(format!("bytes={start}-{}", start.saturating_add(max - 1)))
+        }
+        (Err(_), Ok(suffix)) if start.is_empty() => Some(format!("bytes=-{}", suffix.min(max))),
+        _ => None,
+    }
+}
+
+/* Conditional and range headers only apply when the upstream body is passed through */
+fn fetch(
     client: &Client,
     request: &Request,
     path: &str,
-) -> Response {
+    passthrough: bool,
+) -> Result<ureq::Response, Response> {
     let url = format!("{SITE_PREFIX}{}", path);
     let mut req = client.get(&url);
 
+    let forwarded = if passthrough {
+        FORWARDED_CLIENT_HEADERS
+    } else {
+        PROCESSED_CLIENT_HEADERS
+    };
     for header in request
         .headers()
-        .filter(|(h, _)| !FORBIDDEN_SERVER_HEADERS.contains(&h.to_lowercase().as_str()))
+        .filter(|(h, _)| forwarded.contains(&h.to_lowercase().as_str()))
     {
-        req = req.set(header.0, header.1);
+        let capped = header
+            .0
+            .eq_ignore_ascii_case("range")
+            .then(|| capped_range(header.1))
+            .flatten();
+        req = req.set(header.0, capped.as_deref().unwrap_or(header.1));
     }
 
-    let Ok(res) = req.call() else {
-        return Response::text("Error fetching image")
-            .with_status_code(500)
+    let res = match req.call() {
+        Ok(res) => res,
+        Err(ureq::Error::Status(code, _)) => {
+            return Err(Response::text("Error fetching image").with_status_code(code))
+        }
+        Err(_) => return Err(Response::text("Error fetching image").with_status_code(500)),
     };
-    let status = res.status();
 
-    let headers = res
-        .headers_names()
+    if res.status() == 304 {
+        return Ok(res);
+    }
+    if !is_allowed_type(path, res.content_type()) {
+        return Err(Response::text("Upstream did not return an image").with_status_code(502));
+    }
+
+    match res.header("Content-Length").map(|s| s.parse::<usize>()) {
+        Some(Ok(len)) if len > MAX_BODY_SIZE => {
+            Err(Response::text("Image too large").with_status_code(502))
+        }
+        _ => Ok(res),
+    }
+}
+
+fn forwarded_headers(res: &ureq::Response) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+    res.headers_names()
         .iter()
-        .filter(|h| !FORBIDDEN_CLIENT_HEADERS.contains(&h.as_str()))
+        .filter(|h| FORWARDED_SERVER_HEADERS.contains(&h.as_str()))
         .map(|s| (s.clone().into(), res.header(s).unwrap().to_owned().into()))
-        .collect();
+        .collect()
+}
+
+/* Without a length the size limit can only be enforced by buffering */
+fn read_body(res: ureq::Response) -> Result<Vec<u8>, Response> {
+    let mut data = vec![];
+    let read = res
+        .into_reader()
+        .take(MAX_BODY_SIZE as u64 + 1)
+        .read_to_end(&mut data);
+    match read {
+        Ok(len) if len <= MAX_BODY_SIZE => Ok(data),
+        Ok(_) => Err(Response::text("Image too large").with_status_code(502)),
+        Err(_) => Err(Response::text("Error fetching image").with_status_code(500)),
+    }
+}
+
+fn modified_since(since: &str, last_modified: &str) -> bool {
+    match (
+        DateTime::parse_from_rfc2822(since),
+        DateTime::parse_from_rfc2822(last_modified),
+    ) {
+        (Ok(since), Ok(last_modified)) => last_modified > since,
+        _ => true,
+    }
+}
+
+fn variant_response(request: &Request, variant: &Variant) -> Response {
+    let not_modified = request.header("If-None-Match").is_none()
+        && request
+            .header("If-Modified-Since")
+            .is_some_and(|since| !modified_since(since, &variant.last_modified));
+    if not_modified {
+        return Response::text("")
+            .with_status_code(304)
+            .with_etag_keep(variant.etag.clone());
+    }
+
+    let data = &variant.data;
+    let range = request
+        .header("Range")
+        .filter(|_| {
+            request
+                .header("If-Range")
+                .is_none_or(|tag| tag == variant.etag)
+        })
+        .and_then(|range| parse_range(range, data.len()));
+
+    let response = match range {
+        Some(range) => Response::from_data(variant.content_type, data[range.clone()].to_vec())
+            .with_status_code(206)
+            .with_unique_header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", range.start, range.end - 1, data.len()),
+            ),
+        None => Response::from_data(variant.content_type, data.clone()),
+    };
+
+    response
+        .with_unique_header("Accept-Ranges", "bytes")
+        .with_unique_header("Cache-Control", IMMUTABLE_CACHE)
+        .with_unique_header("Last-Modified", variant.last_modified.clone())
+        .with_etag(request, variant.etag.clone())
+}
+
+/* The format is picked from Accept, so shared caches have to keep the variants apart */
+fn resized_proxy(
+    client: &Client,
+    resizer: &Resizer,
+    request: &Request,
+    path: &str,
+    width: u32,
+) -> Response {
+    resized_variant(client, resizer, request, path, width).with_unique_header("Vary", "Accept")
+}
+
+fn resized_variant(
+    client: &Client,
+    resizer: &Resizer,
+    request: &Request,
+    path: &str,
+    width: u32,
+) -> Response {
+    let format = resizer.format_for(request.header("Accept"));
+    let base = path.split_once('?').map_or(path, |(base, _)| base);
+    let key = format!("{}:{width}:{base}", format.content_type());
+    if let Some(variant) = resizer.get(&key) {
+        return variant_response(request, &variant);
+    }
+
+    let res = match fetch(client, request, path, false) {
+        Ok(res) => res,
+        Err(response) => return response,
+    };
+    let status = res.status();
+    let headers = forwarded_headers(&res);
+    let data = match read_body(res) {
+        Ok(data) => data,
+        Err(response) => return response,
+    };
+
+    match resizer.process(&key, &data, width, format) {
+        Some(variant) => variant_response(request, &variant),
+        /* Serve what we got if it can't be decoded */
+        None => Response {
+            status_code: status,
+            headers,
+            data: rouille::ResponseBody::from_data(data),
+            upgrade: None,
+        },
+    }
+}
+
+fn placeholder_proxy(
+    client: &Client,
+    resizer: &Resizer,
+    request: &Request,
+    path: &str,
+    kind: ImagePlaceholders,
+) -> Response {
+    let (path, _) = take_param(path, "width");
+    let base = path.split_once('?').map_or(path.as_str(), |(base, _)| base);
+    let key = format!("placeholder:{}:{base}", kind.as_str());
+    if let Some(variant) = resizer.get(&key) {
+        return variant_response(request, &variant);
+    }
+
+    /* No need to download the full resolution for a handful of pixels */
+    let upstream = if path.starts_with(IMMUTABLE_PATH) {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        format!("{path}{separator}width={PLACEHOLDER_SOURCE_WIDTH}")
+    } else {
+        path.clone()
+    };
+
+    let data = match fetch(client, request, &upstream, false).and_then(read_body) {
+        Ok(data) => data,
+        Err(response) => return response,
+    };
+
+    match resizer.placeholder(&key, &data, kind) {
+        Some(variant) => variant_response(request, &variant),
+        None => Response::text("Failed to create placeholder").with_status_code(502),
+    }
+}
+
+pub fn image_proxy(client: &Client, resizer: &Resizer, request: &Request, path: &str) -> Response {
+    if !is_allowed_path(path) {
+        return Response::text("Path not allowed").with_status_code(403);
+    }
+
+    let (path, placeholder) = take_param(path, "placeholder");
+    let path = path.as_str();
+    match placeholder.as_deref().map(ImagePlaceholders::parse) {
+        Some(ImagePlaceholders::Off) | None => {}
+        Some(_) if !resizer.resizes() => return Response::empty_404(),
+        Some(kind) => return placeholder_proxy(client, resizer, request, path, kind),
+    }
+
+    if let Some(width) = requested_width(path).filter(|_| resizer.resizes()) {
+        return resized_proxy(client, resizer, request, path, width);
+    }
+
+    let res = match fetch(client, request, path, true) {
+        Ok(res) => res,
+        Err(response) => return response,
+    };
+    let status = res.status();
+    let mut headers = forwarded_headers(&res);
+    if path.starts_with(IMMUTABLE_PATH) {
+        headers.retain(|(h, _)| h != "cache-control" && h != "expires");
+        headers.push(("Cache-Control".into(), IMMUTABLE_CACHE.into()));
+    }
 
     let reader = match res.header("Content-Length").map(|s| s.parse::<usize>()) {
-        Some(Ok(len)) => rouille::ResponseBody::from_reader_and_size(res.into_reader(), len),
-        _ => rouille::ResponseBody::from_reader(res.into_reader()),
+        Some(Ok(len)) => {
+            rouille::ResponseBody::from_reader_and_size(res.into_reader().take(len as u64), len)
+        }
+        _ => match read_body(res) {
+            Ok(data) => rouille::ResponseBody::from_data(data),
+            Err(response) => return response,
+        },
     };
 
     rouille::Response {
@@ -52,4 +339,73 @@ pub fn image_proxy(
         data: reader,
         upgrade: None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_paths() {
+        assert!(is_allowed_path("resizer/v2/abc.jpg?auth=x&width=480"));
+        assert!(is_allowed_path("arc/outboundfeeds/a.png"));
+        assert!(!is_allowed_path("graphics/a.png"));
+        assert!(!is_allowed_path("resizer/../account"));
+        assert!(!is_allowed_path("resizer/%2e%2e/account"));
+        assert!(!is_allowed_path("resizer/%2E./account"));
+    }
+
+    #[test]
+    fn allowed_types() {
+        assert!(is_allowed_type("resizer/a.jpg", "image/jpeg"));
+        assert!(!is_allowed_type("resizer/a.svg", "image/svg+xml"));
+        assert!(!is_allowed_type("arc/a.html", "text/html"));
+        assert!(!is_allowed_type("resizer/a.mp4", "video/mp4"));
+        assert!(is_allowed_type("arc/video/a.mp4", "video/mp4"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(0..100));
+        assert_eq!(parse_range("bytes=900-", 1000), Some(900..1000));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(900..1000));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Some(990..1000));
+        assert_eq!(
+            parse_range(&format!("bytes=0-{}", usize::MAX), 10),
+            Some(0..10)
+        );
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn ranges_stay_below_the_size_limit() {
+        let last = MAX_BODY_SIZE - 1;
+        assert_eq!(capped_range("bytes=0-99"), Some("bytes=0-99".to_owned()));
+        assert_eq!(capped_range("bytes=0-"), Some(format!("bytes=0-{last}")));
+        assert_eq!(
+            capped_range("bytes=100-"),
+            Some(format!("bytes=100-{}", last + 100))
+        );
+        assert_eq!(
+            capped_range(&format!("bytes=0-{}", u64::MAX)),
+            Some(format!("bytes=0-{last}"))
+        );
+        assert_eq!(
+            capped_range("bytes=-999999999"),
+            Some(format!("bytes=-{MAX_BODY_SIZE}"))
+        );
+        assert_eq!(capped_range("bytes=0-1,5-9"), None);
+    }
+
+    #[test]
+    fn modification_dates() {
+        let modified = |since| modified_since(since, "Mon, 19 Oct 2026 07:00:00 GMT");
+        assert!(!modified("Mon, 19 Oct 2026 07:00:00 GMT"));
+        assert!(!modified("Mon, 19 Oct 2026 08:00:00 GMT"));
+        assert!(!modified("Mon, 19 Oct 2026 09:00:00 +0100"));
+        assert!(modified("Sun, 18 Oct 2026 07:00:00 GMT"));
+        assert!(modified("yesterday"));
+    }
+}
@@ -0,0 +1,239 @@
+use rouille::Response;
+
+use crate::{
+    api::{error::ApiResult, redirect::load_redirect},
+    client::Client,
+};
+
+/* Hosts serving the same paths as www.reuters.com */
+const REUTERS_HOSTS: &[&str] = &[
+    "reuters.com",
+    "www.reuters.com",
+    "mobile.reuters.com",
+    "m.reuters.com",
+    "amp.reuters.com",
+    "uk.reuters.com",
+    "ca.reuters.com",
+    "in.reuters.com",
+    "af.reuters.com",
+];
+
+/* Link shorteners, resolved upstream before normalizing */
+const SHORTENER_HOSTS: &[&str] = &["reut.rs"];
+
+/* Query parameters that only serve tracking or alternate page layouts */
+const STRIPPED_PARAMS: &[&str] = &[
+    "taid",
+    "rpc",
+    "fbclid",
+    "gclid",
+    "mc_cid",
+    "mc_eid",
+    "ref",
+    "il",
+    "cmpid",
+    "outputType",
+    "edition-redirect",
+];
+
+const MAX_REDIRECTS: usize = 3;
+
+/* Splits any spelling of a Reuters link into host, path and query */
+fn split_url(url: &str) -> (String, &str, &str) {
+    let url = url.trim();
+    let url = url
+        .strip_prefix("https:")
+        .or_else(|| url.strip_prefix("http:"))
+        .unwrap_or(url)
+        .trim_start_matches('/');
+    let url = url.split_once('#').map_or(url, |(url, _)| url);
+    let (url, query) = url.split_once('?').unwrap_or((url, ""));
+    let (host, path) = url.split_once('/').unwrap_or((url, ""));
+    (host.to_lowercase(), path, query)
+}
+
+/* Reuters paths only use these. Backslashes, colons and the like could make browsers
+ * read the redirect as a link to another origin */
+fn is_path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~%+,=/".contains(c)
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    key.starts_with("utm_") || STRIPPED_PARAMS.contains(&key)
+}
+
+/* Maps the path of a Reuters page onto the matching local route */
+fn local_path(path: &str, query: &str) -> Option<String> {
+    let path = path.trim_start_matches('/');
+    /* AMP pages live below an extra amp/ segment */
+    let path = match (path.strip_prefix("amp/"), path.strip_prefix("article/amp/")) {
+        (Some(path), _) => path.to_owned(),
+        (_, Some(path)) => format!("article/{path}"),
+        _ => path.to_owned(),
+    };
+    if !path.chars().all(is_path_char)
+        || path.starts_with('/')
+        || path.contains("//")
+        || query.contains(char::is_control)
+    {
+        return None;
+    }
+
+    let params: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| !is_tracking_param(pair.split_once('=').map_or(pair, |(key, _)| key)))
+        .collect();
+
+    if path.starts_with("pf/") {
+        return None;
+    }
+    if path.starts_with("resizer/") || path.starts_with("arc/") {
+        return Some(with_query(format!("/proxy/{path}"), &params));
+    }
+    if path.trim_end_matches('/') == "site-search" {
+        let params: Vec<&str> = params
+            .into_iter()
+            .filter(|pair| pair.starts_with("query="))
+            .collect();
+        return Some(with_query("/search".to_owned(), &params));
+    }
+
+    /* Pages end in a slash, except legacy articles and files */
+    let last = path.rsplit('/').next().unwrap_or_default();
+    let path = if path.is_empty()
+        || path.ends_with('/')
+        || path.starts_with("article/")
+        || last.contains('.')
+    {
+        format!("/{path}")
+    } else {
+        format!("/{path}/")
+    };
+    Some(with_query(path, &params))
+}
+
+fn with_query(path: String, params: &[&str]) -> String {
+    if params.is_empty() {
+        path
+    } else {
+        format!("{path}?{}", params.join("&"))
+    }
+}
+
+/* Only pages redirect upstream, proxied files and searches are passed on as they are */
+fn is_page(path: &str) -> bool {
+    !path.starts_with("/proxy/") && !path.starts_with("/search")
+}
+
+/* Shortened links and moved Reuters pages are followed upstream, until a page answers */
+pub fn resolve(client: &Client, url: &str) -> ApiResult<Option<String>> {
+    let mut url = url.to_owned();
+    let mut resolved = None;
+    for _ in 0..=MAX_REDIRECTS {
+        let (host, path, query) = split_url(&url);
+        let target = if REUTERS_HOSTS.contains(&host.as_str()) {
+            let Some(path) = local_path(path, query) else {
+                return Ok(None);
+            };
+            if !is_page(&path) {
+                return Ok(Some(path));
+            }
+            let target = format!("https://www.reuters.com{path}");
+            resolved = Some(path);
+            target
+        } else if SHORTENER_HOSTS.contains(&host.as_str()) {
+            format!("https://{host}/{path}")
+        } else {
+            return Ok(None);
+        };
+
+        url = match load_redirect(client, &target) {
+            Ok((_, location)) if location.starts_with('/') && !location.starts_with("//") => {
+                format!("https://{host}{location}")
+            }
+            Ok((_, location)) => location,
+            /* Anything but a redirect leaves the page where it is, the route shows errors */
+            Err(_) if resolved.is_some() => return Ok(resolved),
+            Err(err) => return Err(err),
+        };
+    }
+    Ok(resolved)
+}
+
+/* Handles /go?url=<link> and links pasted straight after the domain, i.e. /https://www.reuters.com/... */
+pub fn handle_resolve(client: &Client, url: &str) -> ApiResult<Response> {
+    match resolve(client, url)? {
+        Some(location) => Ok(Response::redirect_302(location)),
+        None => Ok(Response::text("Not a supported Reuters link").with_status_code(400)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(url: &str) -> Option<String> {
+        let (_, path, query) = split_url(url);
+        local_path(path, query)
+    }
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(
+            split_url(" https://Mobile.Reuters.com/world/?taid=1#top "),
+            ("mobile.reuters.com".to_owned(), "world/", "taid=1")
+        );
+        assert_eq!(
+            split_url("www.reuters.com/world"),
+            ("www.reuters.com".to_owned(), "world", "")
+        );
+    }
+
+    #[test]
+    fn maps_reuters_paths() {
+        assert_eq!(
+            local("https://www.reuters.com/world/europe/some-story-2024-01-01"),
+            Some("/world/europe/some-story-2024-01-01/".to_owned())
+        );
+        assert_eq!(
+            local("https://www.reuters.com/amp/world/story/?utm_source=x&taid=1&id=2"),
+            Some("/world/story/?id=2".to_owned())
+        );
+        assert_eq!(
+            local("https://www.reuters.com/article/amp/us-story-idUSKBN1"),
+            Some("/article/us-story-idUSKBN1".to_owned())
+        );
+        assert_eq!(
+            local("https://www.reuters.com/resizer/abc.jpg?width=100&ref=x"),
+            Some("/proxy/resizer/abc.jpg?width=100".to_owned())
+        );
+        assert_eq!(
+            local("https://www.reuters.com/site-search/?query=oil&page=2"),
+            Some("/search?query=oil".to_owned())
+        );
+        assert_eq!(local("https://www.reuters.com"), Some("/".to_owned()));
+        assert_eq!(local("https://www.reuters.com/pf/resources/x.js"), None);
+    }
+
+    #[test]
+    fn stays_on_this_origin() {
+        for url in [
+            "https://www.reuters.com/\\evil.com",
+            "https://www.reuters.com/%5Cevil.com\\",
+            "https://www.reuters.com/world//evil.com",
+            "https://www.reuters.com/amp//evil.com",
+            "https://www.reuters.com/https://evil.com/",
+            "https://www.reuters.com/user@evil.com/",
+            "https://www.reuters.com/world/\tstory/",
+            "https://www.reuters.com/world/?a=1\r\nSet-Cookie:x",
+        ] {
+            assert_eq!(local(url), None, "{url:?}");
+        }
+        /* Leading slashes are collapsed into one */
+        assert_eq!(
+            local("https://www.reuters.com//evil.com"),
+            Some("/evil.com".to_owned())
+        );
+    }
+}
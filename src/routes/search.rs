@@ -1,45 +1,84 @@
 use crate::api::{
-    common::Articles, error::ApiResult, search::fetch_articles_by_search,
-    section::fetch_articles_by_section, topic::fetch_articles_by_topic,
+    common::{Article, Articles, Pagination},
+    error::{ApiError, ApiResult},
+    search::{fetch_articles_by_search, SearchFilters, SearchOrder},
+    section::fetch_articles_by_section,
+    topic::fetch_articles_by_topic,
 };
 use crate::client::Client;
+use crate::hierarchy::{SectionMap, ROOT_SECTION};
+use crate::local_index::{self, Document};
+use crate::pagination::{encode_param, render_pagination, Page, SEARCH_SIZES};
+use crate::render::breadcrumbs::{render_breadcrumbs, section_trail};
+use crate::render::follow::render_follow_button;
+use crate::render::images::render_image;
+use crate::render::listing::render_highlighted_listing;
+use crate::search_guard::{
+    self, check_query, Admission, CHALLENGE_DELAY, QUERY_MAX_CHARS, QUERY_MIN_CHARS,
+};
+use crate::settings::Settings;
 use crate::{document, Section};
+use chrono::{DateTime, NaiveDate, Utc};
 use maud::{html, Markup};
+use rouille::Response;
 
-#[derive(PartialEq)]
-enum SearchType {
+pub enum SearchType<'a> {
     Topic,
     Section,
-    Query,
+    Query(&'a SearchQuery),
 }
 
-pub fn render_topic(client: &Client, path: &str, offset: u32, size: u32) -> ApiResult<String> {
-    let article = fetch_articles_by_topic(client, path, offset, size)?;
-    let title = article
-        .topics
-        .as_ref()
-        .and_then(|t| t.first().map(|t| t.name.as_str()))
-        .unwrap_or("");
-    let trailer = html! { h1 { (title) } };
-    render_articles(article, path, offset, size, SearchType::Topic, trailer)
+pub fn render_topic(
+    client: &Client,
+    path: &str,
+    page: &Page,
+    settings: &Settings,
+) -> ApiResult<String> {
+    let (offset, article) =
+        page.fetch(|offset, size| fetch_articles_by_topic(client, path, offset, size))?;
+    let topic = article.topics.as_ref().and_then(|t| t.first());
+    let title = topic.map(|t| t.name.as_str()).unwrap_or("");
+    let trailer = html! {
+        @if let Some(photo) = topic.and_then(|t| t.thumbnail.as_ref()) {
+            @if settings.embed_images {
+                div.portrait { (render_image(photo, settings)) }
+            }
+        }
+        h1 { (title) }
+        (render_follow_button(path, settings))
+    };
+    render_articles(
+        article,
+        path,
+        page,
+        offset,
+        SearchType::Topic,
+        trailer,
+        settings,
+    )
 }
 
 pub fn render_section(
     client: &Client,
+    sections: &SectionMap,
     section: &Section,
-    offset: u32,
-    size: u32,
+    page: &Page,
+    settings: &Settings,
 ) -> ApiResult<String> {
-    let article = fetch_articles_by_section(client, &section.id, offset, size)?;
+    let (offset, article) =
+        page.fetch(|offset, size| fetch_articles_by_section(client, &section.id, offset, size))?;
+    let trail = section_trail(sections, &section.id);
     let trailer = html! {
         div {
+            (render_breadcrumbs(&trail[..trail.len().saturating_sub(1)], settings))
             h1 { (section.name) }
+            (render_follow_button(&section.id, settings))
             @if !section.children.is_empty() {
                 details {
                     summary { "Subsections" }
                     ul {
                         @for child in &section.children {
-                            li { a href=(child.id) { (child.name) } }
+                            li { a href=(settings.link(&child.id)) { (child.name) } }
                         }
                     }
                 }
@@ -49,103 +88,480 @@ pub fn render_section(
     render_articles(
         article,
         &section.id,
+        page,
         offset,
-        size,
         SearchType::Section,
         trailer,
+        settings,
     )
 }
 
-pub fn render_search(client: &Client, request: &rouille::Request) -> ApiResult<String> {
-    return Err(crate::api::error::ApiError::Internal("Search is currently disabled due to abuse".to_string()));
-    match request.get_param("query") {
-        Some(query) => {
-            let offset = request
-                .get_param("offset")
-                .map_or(0, |s| s.parse::<u32>().unwrap_or(0));
-            let size = request
-                .get_param("size")
-                .map_or(20, |s| s.parse::<u32>().unwrap_or(20))
-                .clamp(1, 20);
-
-            let articles = fetch_articles_by_search(client, &query, offset, size)?;
-
-            let trailer = html! {
-                form {
-                    input type="text" name="query" placeholder="Keywords..." value=(query) required="";
-                    button type="submit" { "Search" }
+/* Keyword and filters, as read from the url and written back into pagination links */
+pub struct SearchQuery {
+    keyword: String,
+    section: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    author: Option<String>,
+    order: SearchOrder,
+    /* Searches the articles indexed by this instance instead of upstream */
+    local: bool,
+}
+
+const AUTHOR_MAX_CHARS: usize = 60;
+
+/* Empty form fields count as missing */
+fn param(request: &rouille::Request, name: &str) -> Option<String> {
+    request
+        .get_param(name)
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+fn date_param(request: &rouille::Request, name: &str) -> Result<Option<NaiveDate>, String> {
+    param(request, name)
+        .map(|date| {
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| format!("Dates are written as YYYY-MM-DD, got {date}"))
+        })
+        .transpose()
+}
+
+impl SearchQuery {
+    fn from_request(
+        request: &rouille::Request,
+        sections: &SectionMap,
+        keyword: &str,
+    ) -> Result<Self, String> {
+        let keyword = check_query(keyword)?;
+        let section = param(request, "section");
+        if let Some(section) = section.as_deref().filter(|id| !sections.contains_key(*id)) {
+            return Err(format!("Unknown section {section}"));
+        }
+        let (from, to) = (date_param(request, "from")?, date_param(request, "to")?);
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                return Err("The start date is after the end date".to_owned());
+            }
+        }
+        let author = param(request, "author");
+        if author
+            .as_ref()
+            .is_some_and(|author| author.chars().count() > AUTHOR_MAX_CHARS)
+        {
+            return Err(format!(
+                "Author names are limited to {AUTHOR_MAX_CHARS} characters"
+            ));
+        }
+        let order = match param(request, "sort") {
+            Some(order) => {
+                SearchOrder::parse(&order).ok_or_else(|| format!("Unknown sort order {order}"))?
+            }
+            None => SearchOrder::default(),
+        };
+        let local = !search_guard::enabled() || param(request, "local").as_deref() == Some("1");
+        if local && !local_index::enabled() {
+            return Err("The local index is disabled on this instance".to_owned());
+        }
+
+        Ok(Self {
+            keyword,
+            section,
+            from,
+            to,
+            author,
+            order,
+            local,
+        })
+    }
+
+    /* Only what differs from the defaults, so plain searches keep short urls */
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("query", self.keyword.clone())];
+        if let Some(section) = &self.section {
+            params.push(("section", section.clone()));
+        }
+        if let Some(from) = self.from {
+            params.push(("from", from.to_string()));
+        }
+        if let Some(to) = self.to {
+            params.push(("to", to.to_string()));
+        }
+        if let Some(author) = &self.author {
+            params.push(("author", author.clone()));
+        }
+        if self.order != SearchOrder::default() {
+            params.push(("sort", self.order.as_str().to_owned()));
+        }
+        if self.local {
+            params.push(("local", "1".to_owned()));
+        }
+        params
+    }
+
+    fn query_string(&self) -> String {
+        self.params()
+            .iter()
+            .map(|(key, value)| format!("{key}={}", encode_param(value)))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    fn filters(&self) -> SearchFilters {
+        SearchFilters {
+            section: self.section.clone(),
+            start_date: self.from.map(|date| format!("{date}T00:00:00.000Z")),
+            end_date: self.to.map(|date| format!("{date}T23:59:59.999Z")),
+            order: self.order,
+        }
+    }
+
+    fn has_filters(&self) -> bool {
+        self.params().len() > 1
+    }
+
+    /* Upstream filters, applied by the index itself for local searches */
+    fn matches_document(&self, document: &Document) -> bool {
+        let published = document
+            .published_time
+            .parse::<DateTime<Utc>>()
+            .ok()
+            .map(|time| time.date_naive());
+        let in_range = match (published, self.from, self.to) {
+            (_, None, None) => true,
+            (None, _, _) => false,
+            (Some(date), from, to) => {
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            }
+        };
+        let author = self.author.as_ref().map(|author| author.to_lowercase());
+        in_range
+            && self
+                .section
+                .as_ref()
+                .is_none_or(|section| document.section.starts_with(section.as_str()))
+            && author.is_none_or(|author| {
+                document
+                    .authors
+                    .iter()
+                    .any(|name| name.to_lowercase().contains(&author))
+            })
+    }
+
+    /* Words of the keyword, highlighted in the results */
+    fn terms(&self) -> Vec<String> {
+        self.keyword
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|word| word.chars().count() >= QUERY_MIN_CHARS)
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /* Pages can only be anchored to a publish time when the newest come first */
+    fn is_anchored(&self) -> bool {
+        self.order == SearchOrder::Newest
+    }
+
+    /* The search endpoint has no author filter, so it applies to each fetched page.
+     * The local index filters by author itself. */
+    fn filters_page(&self) -> bool {
+        self.author.is_some() && !self.local
+    }
+
+    fn matches_author(&self, article: &Article) -> bool {
+        let Some(author) = &self.author else {
+            return true;
+        };
+        let author = author.to_lowercase();
+        article
+            .authors
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .any(|topic| topic.name.to_lowercase().contains(&author))
+    }
+}
+
+fn render_search_form(
+    keyword: &str,
+    query: Option<&SearchQuery>,
+    sections: &SectionMap,
+    error: Option<&str>,
+    settings: &Settings,
+) -> String {
+    document!(
+        settings,
+        "Neuters - Reuters Proxy - Search",
+        html! {
+            h1 { "Search:" }
+            @if let Some(error) = error {
+                p { i { (error) } }
+            }
+            (search_form(keyword, query, sections, settings))
+        },
+    )
+    .into_string()
+}
+
+fn search_form(
+    keyword: &str,
+    query: Option<&SearchQuery>,
+    sections: &SectionMap,
+    settings: &Settings,
+) -> Markup {
+    let top_level = sections
+        .get(ROOT_SECTION)
+        .map(|root| root.children.as_slice())
+        .unwrap_or_default();
+    let section = query.and_then(|query| query.section.as_deref());
+    let order = query.map(|query| query.order).unwrap_or_default();
+    let date = |date: Option<NaiveDate>| date.map(|date| date.to_string());
+
+    html! {
+        form.search action=(settings.link("/search")) {
+            input type="text" name="query" placeholder="Keywords..." value=(keyword)
+                minlength=(QUERY_MIN_CHARS) maxlength=(QUERY_MAX_CHARS) required="";
+            button type="submit" { "Search" }
+            details open[query.is_some_and(SearchQuery::has_filters)] {
+                summary { "Filters" }
+                label for="section" {
+                    "Section"
+                    select id="section" name="section" {
+                        option value="" { "All sections" }
+                        /* Keeps a subsection picked from a section page selectable */
+                        @if let Some(section) = section.filter(|id| !top_level.iter().any(|child| child.id == *id)) {
+                            option value=(section) selected { (sections.get(section).map_or(section, |s| s.name.as_str())) }
+                        }
+                        @for child in top_level {
+                            option value=(child.id) selected[section == Some(child.id.as_str())] { (child.name) }
+                        }
+                    }
+                }
+                label for="from" {
+                    "Published from"
+                    input type="date" id="from" name="from" value=[date(query.and_then(|query| query.from))];
                 }
-            };
-
-            render_articles(articles, &query, offset, size, SearchType::Query, trailer)
-        }
-        _ => {
-            let doc = document!(
-                "Neuters - Reuters Proxy - Search",
-                html! {
-                    h1 { "Search:" }
-                    form {
-                        input type="text" name="query" placeholder="Keywords..." required="";
-                        button type="submit" { "Search" }
+                label for="to" {
+                    "Published until"
+                    input type="date" id="to" name="to" value=[date(query.and_then(|query| query.to))];
+                }
+                label for="author" {
+                    "Author"
+                    @if search_guard::enabled() {
+                        " (only filters the results of each page)"
                     }
-                },
-            );
+                    input type="text" id="author" name="author" maxlength=(AUTHOR_MAX_CHARS)
+                        value=[query.and_then(|query| query.author.as_deref())];
+                }
+                @if local_index::enabled() && search_guard::enabled() {
+                    label for="local" {
+                        input type="checkbox" id="local" name="local" value="1"
+                            checked[query.is_some_and(|query| query.local)];
+                        " Only articles read on this instance"
+                    }
+                }
+                label for="sort" {
+                    "Sort by"
+                    select id="sort" name="sort" {
+                        @for choice in SearchOrder::ALL {
+                            option value=(choice.as_str()) selected[choice == order] {
+                                (match choice {
+                                    SearchOrder::Relevance => "Relevance",
+                                    SearchOrder::Newest => "Newest first",
+                                    SearchOrder::Oldest => "Oldest first",
+                                })
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
 
-            Ok(doc.into_string())
+/* Reloads the same search with the challenge token once the delay has passed, no scripts needed */
+fn render_challenge(query: &SearchQuery, page: &Page, token: &str, settings: &Settings) -> String {
+    let url = settings.link(&format!(
+        "/search?{}&size={}&offset={}&challenge={token}",
+        query.query_string(),
+        page.size,
+        page.offset
+    ));
+
+    document!(
+        settings,
+        "Neuters - Reuters Proxy - Search",
+        html! {
+            h1 { "Just a moment" }
+            p {
+                "This instance is receiving many searches from your address. "
+                "Your results will load in " (CHALLENGE_DELAY) " seconds."
+            }
+            p { a href=(url) { "Continue" } }
+        },
+        html! {
+            meta http-equiv="refresh" content=(format!("{CHALLENGE_DELAY};url={url}"));
         }
+    )
+    .into_string()
+}
+
+/* Upstream search, the local index or both */
+pub fn search_enabled() -> bool {
+    search_guard::enabled() || local_index::enabled()
+}
+
+pub fn render_search(
+    client: &Client,
+    sections: &SectionMap,
+    request: &rouille::Request,
+    settings: &Settings,
+) -> ApiResult<Response> {
+    if !search_enabled() {
+        return Err(ApiError::Empty);
+    }
+
+    let Some(keyword) = request.get_param("query") else {
+        return Ok(Response::html(render_search_form(
+            "", None, sections, None, settings,
+        )));
+    };
+    let query = match SearchQuery::from_request(request, sections, &keyword) {
+        Ok(query) => query,
+        Err(err) => {
+            return Ok(Response::html(render_search_form(
+                &keyword,
+                None,
+                sections,
+                Some(&err),
+                settings,
+            ))
+            .with_status_code(400))
+        }
+    };
+    let mut page = Page::from_request(request, &SEARCH_SIZES);
+    if !query.is_anchored() {
+        page.before = None;
+    }
+
+    let Some(guard) = search_guard::guard() else {
+        return Err(ApiError::Empty);
+    };
+
+    /* Challenges are bound to the whole search, filters included. Local searches are
+     * limited the same way, scoring the index isn't free either. */
+    let key = query.query_string();
+    match guard.admit(request, &key) {
+        Admission::Allowed => {}
+        Admission::Challenge(token) => {
+            return Ok(
+                Response::html(render_challenge(&query, &page, &token, settings))
+                    .with_status_code(429)
+                    .with_unique_header("Retry-After", CHALLENGE_DELAY.to_string()),
+            )
+        }
+        Admission::Blocked => {
+            return Ok(Response::text("Too many searches, try again in a minute")
+                .with_status_code(429)
+                .with_unique_header("Retry-After", "60"))
+        }
+    }
+
+    if let Some(index) = local_index::index().filter(|_| query.local) {
+        let terms: Vec<String> = local_index::tokenize(&query.keyword).collect();
+        let urls = index.search(&terms, query.order, |document| {
+            query.matches_document(document)
+        });
+        let (offset, articles) = page.fetch(|offset, size| {
+            let start = (offset as usize).min(urls.len());
+            let end = (start + size as usize).min(urls.len());
+            Ok(Articles {
+                pagination: Pagination {
+                    total_size: Some(urls.len() as u32),
+                },
+                articles: (start < end)
+                    .then(|| index.articles(&urls[start..end], &terms).into_boxed_slice()),
+                topics: None,
+            })
+        })?;
+        return render_articles(
+            articles,
+            "/search",
+            &page,
+            offset,
+            SearchType::Query(&query),
+            search_form(&query.keyword, Some(&query), sections, settings),
+            settings,
+        )
+        .map(Response::html);
     }
+
+    let filters = query.filters();
+    let (offset, articles) = page.fetch(|offset, size| {
+        guard.fetch(&key, offset, size, || {
+            fetch_articles_by_search(client, &query.keyword, &filters, offset, size)
+        })
+    })?;
+
+    render_articles(
+        articles,
+        "/search",
+        &page,
+        offset,
+        SearchType::Query(&query),
+        search_form(&query.keyword, Some(&query), sections, settings),
+        settings,
+    )
+    .map(Response::html)
 }
 
-fn render_articles(
+pub fn render_articles(
     articles: Articles,
     path: &str,
+    page: &Page,
     offset: u32,
-    steps: u32,
     search_type: SearchType,
     trailer: Markup,
+    settings: &Settings,
 ) -> ApiResult<String> {
-    let url = match search_type {
-        SearchType::Section => format!("{path}?"),
-        SearchType::Topic => format!("{path}?"),
-        SearchType::Query => format!("/search?query={path}&"),
+    let (url, params) = match search_type {
+        SearchType::Section | SearchType::Topic => (format!("{}?", settings.link(path)), vec![]),
+        SearchType::Query(query) => (
+            format!("{}?{}&", settings.link(path), query.query_string()),
+            query.params(),
+        ),
     };
+    let hidden: Vec<(&str, &str)> = params
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .collect();
+    let (terms, query) = match search_type {
+        SearchType::Query(query) => (query.terms(), Some(query)),
+        _ => (vec![], None),
+    };
+    let anchor = query.is_none_or(SearchQuery::is_anchored);
+    let page_filter = query.filter(|query| query.filters_page());
 
-    let count = articles
-        .articles
-        .as_ref()
-        .map(|a| a.len() as u32)
-        .unwrap_or(0);
     let total = articles.pagination.total_size.unwrap_or(0);
-    let (has_prev, has_next) = (offset > 0, offset + count < total);
-    let prev_page = if has_prev {
-        let offset = offset.saturating_sub(steps);
-        Some(format!("{url}steps={steps}&offset={offset}"))
-    } else {
-        None
-    };
-    let next_page = if has_next {
-        let offset = offset.saturating_add(steps).min(total - 1);
-        Some(format!("{url}steps={steps}&offset={offset}"))
-    } else {
-        None
-    };
 
     let doc = document!(
+        settings,
         "Neuters - Reuters Proxy",
         html! {
             (trailer)
             @if let Some(articles) = articles.articles {
-                ul {
-                    @for article in articles.iter() {
-                        li { a href=(&article.canonical_url) { (&article.title) } }
-                    }
+                @let shown: Vec<&Article> = articles
+                    .iter()
+                    .filter(|article| page_filter.is_none_or(|query| query.matches_author(article)))
+                    .collect();
+                /* Counts and pages stay those of the unfiltered results */
+                @if page_filter.is_some() {
+                    p { i { (shown.len()) " of the " (articles.len()) " results on this page match the author." } }
                 }
-                @if has_prev || has_next {
-                    div.nav {
-                        a href=[prev_page] { "<" }
-                        ((offset + 1)) " to " ((offset + count)) " of " (total)
-                        a href=[next_page] { ">" }
-                    }
+                (render_highlighted_listing(shown.into_iter(), &terms, settings))
+                @if offset > 0 || offset + (articles.len() as u32) < total {
+                    (render_pagination(&url, &hidden, page, anchor, offset, &articles, total))
                 }
             } @else {
                 p { "No results found!" }
@@ -0,0 +1,42 @@
+use maud::{html, Markup};
+
+use crate::{
+    api::error::ApiResult,
+    document,
+    hierarchy::{SectionMap, ROOT_SECTION},
+    settings::Settings,
+    Section,
+};
+
+fn render_tree(sections: &SectionMap, section: &Section, settings: &Settings) -> Markup {
+    html! {
+        @if !section.children.is_empty() {
+            ul {
+                @for child in &section.children {
+                    li {
+                        a href=(settings.link(&child.id)) { (child.name) }
+                        @if let Some(child) = sections.get(&child.id) {
+                            (render_tree(sections, child, settings))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* Directory of the whole site hierarchy */
+pub fn render_sections(sections: &SectionMap, settings: &Settings) -> ApiResult<String> {
+    let doc = document!(
+        settings,
+        "Sections",
+        html! {
+            h1 { "Sections" }
+            @if let Some(root) = sections.get(ROOT_SECTION) {
+                (render_tree(sections, root, settings))
+            }
+        },
+    );
+
+    Ok(doc.into_string())
+}
@@ -1,117 +1,219 @@
 use maud::{html, Markup};
-use rouille::{post_input, try_or_400, Request, Response};
+use rouille::{input::post::raw_urlencoded_post_input, try_or_400, Request, Response};
 
 use crate::{
     document,
-    settings::{Settings, EMBED_EMBEDS, EMBED_IMAGES, FAST_REDIRECT, PROXY_IMAGES, REDIRECT_TIMER},
+    hierarchy::SectionMap,
+    settings::{is_followable, normalize_follow, token_prefix, SettingValue, Settings, SETTINGS},
 };
 
-fn render_settings(
-    embed_images: bool,
-    embed_embeds: bool,
-    proxy_images: bool,
-    fast_redirect: bool,
-    redirect_timer: u32,
-) -> Markup {
+fn render_settings(settings: &Settings, url_token: Option<&str>) -> Markup {
+    let token = settings.to_token();
+    let link = format!("{}/", token_prefix(&token));
+
     document!(
+        settings,
         "Settings",
         html! {
             h1 { "Settings" }
-            p {
-                "Settings will be stored in the browsers cookie storage and transferred to the server on each request. This site only uses cookies to store preferences and does not track you."
+            @if url_token.is_some() {
+                p {
+                    "Settings are stored in the address of this page, no cookies are used. Bookmark any page to keep them."
+                }
+            } @else {
+                p {
+                    "Settings will be stored in the browsers cookie storage and transferred to the server on each request. This site only uses cookies to store preferences and does not track you."
+                }
+                p {
+                    "To use these settings without cookies, bookmark "
+                    a href=(link) { "this link" }
+                    "."
+                }
             }
             form method="POST" {
-                label for=(EMBED_IMAGES) {
-                    "Embed images in articles"
-                    input type="checkbox" id=(EMBED_IMAGES) name=(EMBED_IMAGES) checked[embed_images] {}
+                @for def in SETTINGS {
+                    label for=(def.key) {
+                        (def.label)
+                        ((def.input)(settings))
+                    }
                 }
 
-                label for=(EMBED_EMBEDS) {
-                    "Embed embeds in articles"
-                    input type="checkbox" id=(EMBED_EMBEDS) name=(EMBED_EMBEDS) checked[embed_embeds] {}
+                button type="submit" {
+                    "Save"
                 }
-
-                label for=(PROXY_IMAGES) {
-                    "Proxy images through the server"
-                    input type="checkbox" id=(PROXY_IMAGES) name=(PROXY_IMAGES) checked[proxy_images] {}
+                @if url_token.is_none() {
+                    " "
+                    button type="submit" name="store" value="link" {
+                        "Save as link"
+                    }
                 }
+            }
 
-                label for=(FAST_REDIRECT) {
-                    "Use fast redirect"
-                    input type="checkbox" id=(FAST_REDIRECT) name=(FAST_REDIRECT) checked[fast_redirect] {}
-                }
+            h2 { "Export" }
+            p {
+                "Paste this string into the settings page of any other instance to take your preferences along."
+            }
+            input type="text" readonly value=(token) {}
 
-                label for=(REDIRECT_TIMER) {
-                    "Redirect timer"
-                    input type="number" id=(REDIRECT_TIMER) name=(REDIRECT_TIMER) value=(redirect_timer) {}
+            h2 { "Import" }
+            form method="POST" {
+                label for="import" {
+                    "Settings string or link"
+                    input type="text" id="import" name="import" {}
                 }
-
                 button type="submit" {
-                    "Save"
+                    "Import"
                 }
             }
         },
     )
 }
 
-fn store_settings(
-    embed_images: bool,
-    embed_embeds: bool,
-    proxy_images: bool,
-    fast_redirect: bool,
-    redirect_timer: u32,
-) -> Response {
-    Response::redirect_303("/settings")
-        .with_additional_header(
-            "Set-Cookie",
-            format!("{EMBED_IMAGES}={}; Path=/; SameSite=Strict", embed_images),
-        )
-        .with_additional_header(
+fn store_settings(settings: &Settings) -> Response {
+    let mut response = Response::redirect_303("/settings");
+    for def in SETTINGS {
+        response = response.with_additional_header(
             "Set-Cookie",
-            format!("{EMBED_EMBEDS}={}; Path=/; SameSite=Strict", embed_embeds),
-        )
-        .with_additional_header(
-            "Set-Cookie",
-            format!("{PROXY_IMAGES}={}; Path=/; SameSite=Strict", proxy_images),
-        )
-        .with_additional_header(
-            "Set-Cookie",
-            format!("{FAST_REDIRECT}={}; Path=/; SameSite=Strict", fast_redirect),
-        )
-        .with_additional_header(
+            format!(
+                "{}={}; Path=/; SameSite=Strict",
+                def.key,
+                (def.read)(settings)
+            ),
+        );
+    }
+    response
+}
+
+/* Follow buttons on listings post here and return to the listing */
+pub fn handle_follow(
+    request: &Request,
+    sections: &SectionMap,
+    settings: &Settings,
+    url_token: Option<&str>,
+) -> Response {
+    let fields = try_or_400!(raw_urlencoded_post_input(request));
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let Some(path) = field("path")
+        .and_then(normalize_follow)
+        .filter(|path| is_followable(sections, path))
+    else {
+        return Response::text("Invalid path").with_status_code(400);
+    };
+
+    let mut settings = settings.clone();
+    match field("action") {
+        Some("unfollow") => settings.follows.unfollow(&path),
+        _ => settings.follows.follow(&path),
+    }
+
+    if url_token.is_some() {
+        Response::redirect_303(format!("{}{path}", token_prefix(&settings.to_token())))
+    } else {
+        Response::redirect_303(path).with_additional_header(
             "Set-Cookie",
             format!(
-                "{REDIRECT_TIMER}={}; Path=/; SameSite=Strict",
-                redirect_timer
+                "follows={}; Path=/; SameSite=Strict",
+                settings.follows.to_setting()
             ),
         )
+    }
 }
 
-pub fn handle_settings(request: &Request, settings: &Settings) -> Response {
+/* Settings carried in the url are posted back into a new url instead of cookies */
+pub fn handle_settings(
+    request: &Request,
+    settings: &Settings,
+    url_token: Option<&str>,
+) -> Response {
     if request.method() == "POST" {
-        let settings = try_or_400!(post_input!(request, {
-            embed_images: bool,
-            embed_embeds: bool,
-            proxy_images: bool,
-            fast_redirect: bool,
-            redirect_timer: i32,
-        }));
-
-        store_settings(
-            settings.embed_images,
-            settings.embed_embeds,
-            settings.proxy_images,
-            settings.fast_redirect,
-            settings.redirect_timer.clamp(0, 600) as u32,
-        )
+        let fields = try_or_400!(raw_urlencoded_post_input(request));
+        let import = fields.iter().find(|(key, _)| key == "import");
+        let settings = match import {
+            Some((_, value)) => match Settings::import(value) {
+                Some(settings) => settings,
+                None => return Response::text("Invalid settings string").with_status_code(400),
+            },
+            None => Settings::from_form(&fields),
+        };
+        let as_link = fields
+            .iter()
+            .any(|(key, value)| key == "store" && value == "link");
+
+        if url_token.is_some() || as_link {
+            /* Token links are never prefixed again */
+            Response::redirect_303(format!("{}/settings", token_prefix(&settings.to_token())))
+        } else {
+            store_settings(&settings)
+        }
     } else {
-        let page = render_settings(
-            settings.embed_images,
-            settings.embed_embeds,
-            settings.proxy_images,
-            settings.fast_redirect,
-            settings.redirect_timer,
-        );
+        let page = render_settings(settings, url_token);
         Response::html(page).with_status_code(200)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sections;
+
+    fn follow(path: &str, url_token: Option<&str>) -> Response {
+        let request = Request::fake_http(
+            "POST",
+            "/follow",
+            vec![(
+                "Content-Type".to_owned(),
+                "application/x-www-form-urlencoded".to_owned(),
+            )],
+            format!("path={path}&action=follow").into_bytes(),
+        );
+        handle_follow(
+            &request,
+            &sections(),
+            &Settings::from_request(&request),
+            url_token,
+        )
+    }
+
+    fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+        response
+            .headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    #[test]
+    fn follows_listings() {
+        let response = follow("/world", None);
+        assert_eq!(response.status_code, 303);
+        assert_eq!(header(&response, "Location"), Some("/world/"));
+        assert_eq!(
+            header(&response, "Set-Cookie"),
+            Some("follows=/world/; Path=/; SameSite=Strict")
+        );
+
+        let response = follow("%2Fauthors%2Fjane-doe%2F", Some("token"));
+        let location = header(&response, "Location").unwrap();
+        assert!(location.starts_with("/s/") && location.ends_with("/authors/jane-doe/"));
+    }
+
+    #[test]
+    fn rejects_other_paths() {
+        for path in [
+            "%2F%5Cevil.com",
+            "%2F%2Fevil.com",
+            "%2Fx%3BDomain%3Devil",
+            "%2Fsports%2F",
+            "https%3A%2F%2Fevil.com%2F",
+        ] {
+            let response = follow(path, None);
+            assert_eq!(response.status_code, 400, "{path}");
+            assert_eq!(header(&response, "Set-Cookie"), None);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use neuters::api::{common::Articles, error::ApiResult};
+use rand::RngCore;
+use rouille::Request;
+use sha2::{Digest, Sha256};
+
+static SEARCH: OnceLock<Option<SearchGuard>> = OnceLock::new();
+
+/* Repeated queries are answered from memory for this long */
+const CACHE_TTL: Duration = Duration::from_secs(600);
+const CACHE_ENTRIES: usize = 512;
+
+/* Buckets of clients idle for this long are dropped once the table grows */
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+const BUCKET_ENTRIES: usize = 4096;
+
+/* Seconds a client out of searches has to wait before the challenge lets it through.
+ * Each solved challenge admits a single search. */
+pub const CHALLENGE_DELAY: u64 = 5;
+const CHALLENGE_TTL: u64 = 600;
+
+pub const QUERY_MIN_CHARS: usize = 2;
+pub const QUERY_MAX_CHARS: usize = 100;
+/* Besides letters, digits and spaces. Quotes and backslashes would end up in the upstream JSON */
+const QUERY_PUNCTUATION: &str = "-'.,&:!?()/$%+";
+
+/* Keyed by lowercased query, offset and size */
+type ResultCache = HashMap<(String, u32, u32), (Instant, Articles)>;
+
+pub enum Admission {
+    Allowed,
+    /* Token to send back after CHALLENGE_DELAY seconds */
+    Challenge(String),
+    Blocked,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /* Challenges handed out since the bucket was last full */
+    strikes: u32,
+}
+
+pub struct SearchGuard {
+    /* Off when only the local index is searched */
+    upstream: bool,
+    /* Searches per minute and client, also the size of a burst */
+    rate: u32,
+    ip_header: Option<String>,
+    secret: [u8; 32],
+    buckets: Mutex<HashMap<String, Bucket>>,
+    /* Solved challenge tokens by the time they were issued, until they expire */
+    spent: Mutex<HashMap<String, u64>>,
+    cache: Mutex<ResultCache>,
+}
+
+/* Search stays off unless the operator turns it on, the limits also apply to searching
+ * the local index */
+pub fn init(upstream: bool, local: bool, rate: u32, ip_header: Option<String>) {
+    let guard = (upstream || local).then(|| SearchGuard::new(upstream, rate, ip_header));
+    if SEARCH.set(guard).is_err() {
+        panic!("Search already initialized");
+    }
+}
+
+pub fn guard() -> Option<&'static SearchGuard> {
+    SEARCH.get().and_then(Option::as_ref)
+}
+
+/* Whether upstream search is on */
+pub fn enabled() -> bool {
+    guard().is_some_and(|guard| guard.upstream)
+}
+
+/* Trims and collapses whitespace, rejecting queries the upstream search can't take */
+pub fn check_query(query: &str) -> Result<String, String> {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    let chars = query.chars().count();
+    if chars < QUERY_MIN_CHARS {
+        return Err(format!(
+            "Queries need at least {QUERY_MIN_CHARS} characters"
+        ));
+    }
+    if chars > QUERY_MAX_CHARS {
+        return Err(format!(
+            "Queries are limited to {QUERY_MAX_CHARS} characters"
+        ));
+    }
+    if let Some(c) = query
+        .chars()
+        .find(|c| !c.is_alphanumeric() && *c != ' ' && !QUERY_PUNCTUATION.contains(*c))
+    {
+        return Err(format!("Queries can't contain '{c}'"));
+    }
+    Ok(query)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+impl SearchGuard {
+    fn new(upstream: bool, rate: u32, ip_header: Option<String>) -> Self {
+        let mut secret = [0; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            upstream,
+            rate: rate.max(1),
+            ip_header,
+            secret,
+            buckets: Mutex::new(HashMap::new()),
+            spent: Mutex::new(HashMap::new()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /* The configured header is only trusted when running behind a proxy that sets it.
+     * Clients can send the header themselves, only the last hop comes from the proxy. */
+    fn client(&self, request: &Request) -> String {
+        self.ip_header
+            .as_deref()
+            .and_then(|header| request.header(header))
+            .and_then(|value| value.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .map_or_else(|| request.remote_addr().ip().to_string(), str::to_owned)
+    }
+
+    fn sign(&self, client: &str, query: &str, issued: u64) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.secret);
+        hasher.update(format!("{client}\n{query}\n{issued}"));
+        hasher.finalize()[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    fn challenge(&self, client: &str, query: &str) -> String {
+        let issued = now();
+        format!("{issued}.{}", self.sign(client, query, issued))
+    }
+
+    /* Valid tokens are spent on the first use */
+    fn solved(&self, client: &str, query: &str, token: &str) -> bool {
+        let Some((issued, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(issued) = issued.parse::<u64>() else {
+            return false;
+        };
+        let now = now();
+        let age = now.saturating_sub(issued);
+        if !(CHALLENGE_DELAY..=CHALLENGE_TTL).contains(&age)
+            || self.sign(client, query, issued) != signature
+        {
+            return false;
+        }
+
+        let mut spent = self.spent.lock().unwrap();
+        spent.retain(|_, issued| now.saturating_sub(*issued) <= CHALLENGE_TTL);
+        spent.insert(signature.to_owned(), issued).is_none()
+    }
+
+    /* A token bucket per client. Once it runs dry every search needs a solved challenge,
+     * clients ignoring those get blocked until their bucket refills. */
+    pub fn admit(&self, request: &Request, query: &str) -> Admission {
+        let client = self.client(request);
+        if let Some(token) = request.get_param("challenge") {
+            if self.solved(&client, query, &token) {
+                return Admission::Allowed;
+            }
+        }
+
+        let rate = f64::from(self.rate);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= BUCKET_ENTRIES && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| bucket.updated.elapsed() < BUCKET_IDLE);
+            /* Still full of active clients, the one idle the longest makes room */
+            if buckets.len() >= BUCKET_ENTRIES {
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(client, _)| client.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+        let bucket = buckets.entry(client.clone()).or_insert(Bucket {
+            tokens: rate,
+            updated: Instant::now(),
+            strikes: 0,
+        });
+
+        let refill = bucket.updated.elapsed().as_secs_f64() * rate / 60.0;
+        bucket.tokens = (bucket.tokens + refill).min(rate);
+        bucket.updated = Instant::now();
+        if bucket.tokens >= rate {
+            bucket.strikes = 0;
+        }
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Admission::Allowed
+        } else if bucket.strikes < self.rate {
+            bucket.strikes += 1;
+            Admission::Challenge(self.challenge(&client, query))
+        } else {
+            Admission::Blocked
+        }
+    }
+
+    /* Results are cached per query and page, whoever asked for them */
+    pub fn fetch(
+        &self,
+        query: &str,
+        offset: u32,
+        size: u32,
+        fetch: impl FnOnce() -> ApiResult<Articles>,
+    ) -> ApiResult<Articles> {
+        let key = (query.to_lowercase(), offset, size);
+        if let Some((fetched, articles)) = self.cache.lock().unwrap().get(&key) {
+            if fetched.elapsed() < CACHE_TTL {
+                return Ok(articles.clone());
+            }
+        }
+
+        let articles = fetch()?;
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_ENTRIES {
+            cache.retain(|_, (fetched, _)| fetched.elapsed() < CACHE_TTL);
+        }
+        if cache.len() < CACHE_ENTRIES {
+            cache.insert(key, (Instant::now(), articles.clone()));
+        }
+        Ok(articles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(forwarded: Option<&str>) -> Request {
+        let headers = forwarded
+            .map(|value| ("X-Forwarded-For".to_owned(), value.to_owned()))
+            .into_iter()
+            .collect();
+        Request::fake_http("GET", "/search?query=oil", headers, vec![])
+    }
+
+    #[test]
+    fn checks_queries() {
+        assert_eq!(check_query("  oil \t prices "), Ok("oil prices".to_owned()));
+        assert_eq!(
+            check_query("S&P 500: up 1.5%?"),
+            Ok("S&P 500: up 1.5%?".to_owned())
+        );
+        assert!(check_query(" a ").is_err());
+        assert!(check_query(&"a".repeat(QUERY_MAX_CHARS + 1)).is_err());
+        assert_eq!(
+            check_query("oil\" OR \\\\"),
+            Err("Queries can't contain '\"'".to_owned())
+        );
+    }
+
+    #[test]
+    fn trusts_only_the_last_hop() {
+        let guard = SearchGuard::new(true, 1, Some("X-Forwarded-For".to_owned()));
+        assert_eq!(guard.client(&request(Some("1.1.1.1, 2.2.2.2"))), "2.2.2.2");
+        assert_eq!(guard.client(&request(Some(" 3.3.3.3 "))), "3.3.3.3");
+        let remote = request(None).remote_addr().ip().to_string();
+        assert_eq!(guard.client(&request(Some("1.1.1.1, "))), remote);
+
+        let direct = SearchGuard::new(true, 1, None);
+        assert_eq!(direct.client(&request(Some("1.1.1.1"))), remote);
+    }
+
+    #[test]
+    fn limits_searches() {
+        let guard = SearchGuard::new(true, 2, None);
+        let request = request(None);
+        assert!(matches!(guard.admit(&request, "oil"), Admission::Allowed));
+        assert!(matches!(guard.admit(&request, "oil"), Admission::Allowed));
+        for _ in 0..2 {
+            assert!(matches!(
+                guard.admit(&request, "oil"),
+                Admission::Challenge(_)
+            ));
+        }
+        assert!(matches!(guard.admit(&request, "oil"), Admission::Blocked));
+    }
+
+    #[test]
+    fn challenges_are_single_use() {
+        let guard = SearchGuard::new(true, 1, None);
+        let client = guard.client(&request(None));
+        let issued = now() - CHALLENGE_DELAY;
+        let token = format!("{issued}.{}", guard.sign(&client, "oil", issued));
+
+        assert!(!guard.solved(&client, "gas", &token));
+        assert!(!guard.solved("1.1.1.1", "oil", &token));
+        assert!(guard.solved(&client, "oil", &token));
+        assert!(!guard.solved(&client, "oil", &token));
+
+        /* Too early, too late */
+        for issued in [now(), now() - CHALLENGE_TTL - 1] {
+            let token = format!("{issued}.{}", guard.sign(&client, "oil", issued));
+            assert!(!guard.solved(&client, "oil", &token));
+        }
+    }
+}
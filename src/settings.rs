@@ -1,43 +1,815 @@
-use rouille::{input, Request};
+use std::{fs, path::Path, sync::OnceLock};
 
-pub const EMBED_IMAGES: &str = "embed_images";
-pub const EMBED_EMBEDS: &str = "embed_embeds";
-pub const PROXY_IMAGES: &str = "proxy_images";
-pub const FAST_REDIRECT: &str = "fast_redirect";
-pub const REDIRECT_TIMER: &str = "redirect_timer";
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use maud::{html, Markup};
+use rouille::{input, Request, Response};
 
-pub struct Settings {
-    pub embed_images: bool,
-    pub embed_embeds: bool,
-    pub proxy_images: bool,
-    pub fast_redirect: bool,
-    pub redirect_timer: u32,
+use crate::hierarchy::SectionMap;
+
+/* Bumped whenever the token layout changes, unknown versions fall back to the defaults */
+const TOKEN_VERSION: char = '1';
+
+/* Tokens only grow with the number of settings, anything longer is not one of ours */
+const TOKEN_MAX_LEN: usize = 4096;
+
+/* Generated links skip these, everything else is prefixed with the settings token */
+const UNPREFIXED_PATHS: &[&str] = &["s/", "proxy/", "static/"];
+
+/* Instance wide defaults configured by the operator, see load_defaults */
+static DEFAULTS: OnceLock<Settings> = OnceLock::new();
+
+/* A type that can be stored in a cookie and edited in the settings form */
+pub trait SettingValue: Sized {
+    fn from_setting(value: &str) -> Option<Self>;
+    fn to_setting(&self) -> String;
+    fn render_input(&self, key: &str) -> Markup;
+
+    /* Value used when the key is missing from a form post, i.e. unchecked checkboxes */
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+impl SettingValue for bool {
+    fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "true" | "on" => Some(true),
+            "false" | "off" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn to_setting(&self) -> String {
+        self.to_string()
+    }
+
+    fn render_input(&self, key: &str) -> Markup {
+        html! { input type="checkbox" id=(key) name=(key) checked[*self] {} }
+    }
+
+    fn missing() -> Option<Self> {
+        Some(false)
+    }
+}
+
+impl SettingValue for u32 {
+    fn from_setting(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn to_setting(&self) -> String {
+        self.to_string()
+    }
+
+    fn render_input(&self, key: &str) -> Markup {
+        html! { input type="number" id=(key) name=(key) value=(self) {} }
+    }
+}
+
+/* Declares an enum of fixed options, edited through a select in the settings form */
+macro_rules! choices {
+    ($name:ident { $( $variant:ident = $value:literal, $label:literal; )* }) => {
+        #[derive(Clone, Copy, PartialEq)]
+        pub enum $name {
+            $( $variant, )*
+        }
+
+        impl $name {
+            pub const ALL: &[Self] = &[$( Self::$variant, )*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $( Self::$variant => $value, )*
+                }
+            }
+
+            pub fn label(self) -> &'static str {
+                match self {
+                    $( Self::$variant => $label, )*
+                }
+            }
+        }
+
+        impl SettingValue for $name {
+            fn from_setting(value: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|option| option.as_str() == value)
+            }
+
+            fn to_setting(&self) -> String {
+                self.as_str().to_owned()
+            }
+
+            fn render_input(&self, key: &str) -> Markup {
+                html! {
+                    select id=(key) name=(key) {
+                        @for option in Self::ALL {
+                            option value=(option.as_str()) selected[option == self] { (option.label()) }
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+choices!(ImagePlaceholders {
+    Off = "off", "Full images";
+    Blur = "blur", "Blurred previews";
+    Color = "color", "Color boxes";
+});
+
+impl ImagePlaceholders {
+    pub fn parse(value: &str) -> Self {
+        Self::from_setting(value).unwrap_or(Self::Off)
+    }
+}
+
+/* Themes are stylesheets compiled from sass/themes, named after the values */
+choices!(Theme {
+    Auto = "auto", "System";
+    Light = "light", "Light";
+    Dark = "dark", "Dark";
+    Sepia = "sepia", "Sepia";
+    HighContrast = "high-contrast", "High contrast";
+});
+
+/* The values double as class names, keep them in sync with sass/includes/typography.scss */
+choices!(FontFamily {
+    Sans = "sans", "Sans-serif";
+    Serif = "serif", "Serif";
+    Mono = "mono", "Monospace";
+    Dyslexia = "dyslexia", "Dyslexia friendly";
+});
+
+choices!(FontSize {
+    Small = "14", "Small";
+    Compact = "16", "Compact";
+    Normal = "18", "Normal";
+    Large = "20", "Large";
+    Larger = "22", "Larger";
+    Largest = "24", "Largest";
+});
+
+choices!(LineHeight {
+    Tight = "12", "Tight";
+    Normal = "14", "Normal";
+    Relaxed = "16", "Relaxed";
+    Loose = "18", "Loose";
+    Double = "20", "Double";
+});
+
+choices!(ListingStyle {
+    Compact = "compact", "Titles only";
+    Cards = "cards", "Cards with summary and thumbnail";
+});
+
+choices!(ContentWidth {
+    Narrow = "narrow", "Narrow";
+    Medium = "medium", "Medium";
+    Wide = "wide", "Wide";
+});
+
+/* Sections, topics and authors merged on /my */
+#[derive(Clone, Default)]
+pub struct Follows(Vec<String>);
+
+impl Follows {
+    pub const MAX: usize = 30;
+
+    pub fn paths(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        normalize_follow(path).is_some_and(|path| self.0.contains(&path))
+    }
+
+    pub fn follow(&mut self, path: &str) {
+        if let Some(path) = normalize_follow(path) {
+            if !self.0.contains(&path) && self.0.len() < Self::MAX {
+                self.0.push(path);
+            }
+        }
+    }
+
+    pub fn unfollow(&mut self, path: &str) {
+        if let Some(path) = normalize_follow(path) {
+            self.0.retain(|followed| *followed != path);
+        }
+    }
+}
+
+const FOLLOW_MAX_CHARS: usize = 100;
+
+/* Listing paths in the charset Reuters uses for them, always ending in a slash. They end up
+ * in cookies and redirects, so nothing else gets through. */
+pub fn normalize_follow(path: &str) -> Option<String> {
+    let valid = path.starts_with('/')
+        && path != "/"
+        && path.len() <= FOLLOW_MAX_CHARS
+        && !path.contains("//")
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_/".contains(c));
+    if !valid {
+        None
+    } else if path.ends_with('/') {
+        Some(path.to_owned())
+    } else {
+        Some(format!("{path}/"))
+    }
+}
+
+/* Existing sections, single authors and topics: the listings /my can merge. Takes
+ * normalized paths. */
+pub fn is_followable(sections: &SectionMap, path: &str) -> bool {
+    let listing = path
+        .strip_prefix("/authors/")
+        .or_else(|| path.strip_prefix("/topic/"))
+        .and_then(|rest| rest.strip_suffix('/'));
+    sections.contains_key(path)
+        || listing.is_some_and(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/* Stored with '|' as separator, which is valid in cookies, and edited one path per line */
+impl SettingValue for Follows {
+    fn from_setting(value: &str) -> Option<Self> {
+        let mut follows = Self::default();
+        for path in value.split(['|', '\n', '\r', ' ', ',']) {
+            follows.follow(path);
+        }
+        Some(follows)
+    }
+
+    fn to_setting(&self) -> String {
+        self.0.join("|")
+    }
+
+    fn render_input(&self, key: &str) -> Markup {
+        html! { textarea id=(key) name=(key) rows="4" { (self.0.join("\n")) } }
+    }
+}
+
+pub struct SettingDef {
+    pub key: &'static str,
+    /* Short name used in URL tokens */
+    pub code: &'static str,
+    pub label: &'static str,
+    pub read: fn(&Settings) -> String,
+    pub input: fn(&Settings) -> Markup,
+    valid: fn(&str) -> bool,
+    write: fn(&mut Settings, Option<&str>),
+}
+
+/* Declares the Settings struct and the SETTINGS table from one entry per preference */
+macro_rules! settings {
+    ($( $name:ident($code:literal): $ty:ty = $default:expr, $label:literal $(, validate = $validate:expr)?; )*) => {
+        #[derive(Clone)]
+        pub struct Settings {
+            $( pub $name: $ty, )*
+            /* Token the settings were read from, local links carry it along */
+            pub url_token: Option<String>,
+        }
+
+        impl Settings {
+            /* Defaults shipped with neuters, independent of the instance configuration */
+            fn builtin() -> Self {
+                Self {
+                    $( $name: $default, )*
+                    url_token: None,
+                }
+            }
+        }
+
+        pub const SETTINGS: &[SettingDef] = &[
+            $(
+                SettingDef {
+                    key: stringify!($name),
+                    code: $code,
+                    label: $label,
+                    read: |settings| settings.$name.to_setting(),
+                    input: |settings| settings.$name.render_input(stringify!($name)),
+                    valid: |value| <$ty>::from_setting(value).is_some(),
+                    write: |settings, value| {
+                        let value = match value {
+                            Some(value) => <$ty>::from_setting(value),
+                            None => <$ty>::missing(),
+                        };
+                        if let Some(value) = value {
+                            $( let value = ($validate)(value); )?
+                            settings.$name = value;
+                        }
+                    },
+                },
+            )*
+        ];
+    };
+}
+
+settings! {
+    embed_images("ei"): bool = false, "Embed images in articles";
+    embed_embeds("ee"): bool = false, "Embed embeds in articles";
+    proxy_images("pi"): bool = true, "Proxy images through the server";
+    image_placeholders("ip"): ImagePlaceholders = ImagePlaceholders::Off,
+        "Low bandwidth image previews (requires proxying)";
+    theme("th"): Theme = Theme::Auto, "Theme";
+    font_family("ff"): FontFamily = FontFamily::Sans, "Font";
+    font_size("fs"): FontSize = FontSize::Normal, "Font size";
+    line_height("lh"): LineHeight = LineHeight::Normal, "Line height";
+    content_width("cw"): ContentWidth = ContentWidth::Medium, "Content width";
+    listing_style("ls"): ListingStyle = ListingStyle::Compact, "Story listings";
+    follows("fo"): Follows = Follows::default(),
+        "Followed sections, topics and authors (one path per line)";
+    fast_redirect("fr"): bool = false, "Use fast redirect";
+    redirect_timer("rt"): u32 = 5, "Redirect timer", validate = |timer: u32| timer.min(600);
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        DEFAULTS.get().cloned().unwrap_or_else(Self::builtin)
+    }
 }
 
 impl Settings {
     pub fn from_request(request: &Request) -> Self {
-        let mut embed_images = false;
-        let mut embed_embeds = false;
-        let mut proxy_images = true;
-        let mut fast_redirect = false;
-        let mut redirect_timer = 5;
+        let mut settings = Self::default();
         for (key, value) in input::cookies(request) {
-            match key {
-                EMBED_IMAGES => embed_images = value == "true",
-                EMBED_EMBEDS => embed_embeds = value == "true",
-                PROXY_IMAGES => proxy_images = value == "true",
-                FAST_REDIRECT => fast_redirect = value == "true",
-                REDIRECT_TIMER => redirect_timer = value.parse().unwrap_or(5),
-                _ => {}
+            if let Some(def) = SETTINGS.iter().find(|def| def.key == key) {
+                (def.write)(&mut settings, Some(value));
             }
         }
+        settings
+    }
 
-        Self {
-            embed_images,
-            embed_embeds,
-            proxy_images,
-            fast_redirect,
-            redirect_timer,
+    /* Every setting is part of the form, missing keys fall back to SettingValue::missing */
+    pub fn from_form(fields: &[(String, String)]) -> Self {
+        let mut settings = Self::default();
+        for def in SETTINGS {
+            let value = fields
+                .iter()
+                .find(|(key, _)| key == def.key)
+                .map(|(_, value)| value.as_str());
+            (def.write)(&mut settings, value);
         }
+        settings
+    }
+
+    /* Compact, URL safe encoding of everything changed from the builtin or the instance
+     * defaults. Whatever a token leaves out follows the defaults of the instance reading it. */
+    pub fn to_token(&self) -> String {
+        self.token_against(&Self::default())
     }
-}
\ No newline at end of file
+
+    fn token_against(&self, defaults: &Settings) -> String {
+        let builtin = Self::builtin();
+        let pairs: Vec<String> = SETTINGS
+            .iter()
+            .filter(|def| {
+                let value = (def.read)(self);
+                value != (def.read)(&builtin) || value != (def.read)(defaults)
+            })
+            .map(|def| format!("{}={}", def.code, escape((def.read)(self))))
+            .collect();
+
+        let mut token = TOKEN_VERSION.to_string();
+        token.push_str(&BASE64.encode(pairs.join("&")));
+        token
+    }
+
+    /* Settings for a request under /s/<token>/, links on the page keep the token */
+    pub fn from_token(token: &str) -> Self {
+        let mut settings = Self::parse_token(token).unwrap_or_default();
+        settings.url_token = Some(token.to_owned());
+        settings
+    }
+
+    pub fn parse_token(token: &str) -> Option<Self> {
+        Self::parse_token_onto(token, Self::default())
+    }
+
+    fn parse_token_onto(token: &str, mut settings: Settings) -> Option<Self> {
+        if !is_token(token) {
+            return None;
+        }
+        let data = token.strip_prefix(TOKEN_VERSION)?;
+        let data = String::from_utf8(BASE64.decode(data).ok()?).ok()?;
+
+        for (code, value) in data.split('&').filter_map(|pair| pair.split_once('=')) {
+            if let Some(def) = SETTINGS.iter().find(|def| def.code == code) {
+                (def.write)(&mut settings, Some(&unescape(value)));
+            }
+        }
+        Some(settings)
+    }
+
+    /* Local paths are prefixed with the url token, everything else is left alone */
+    pub fn link(&self, path: &str) -> String {
+        match &self.url_token {
+            Some(token) if is_prefixable(path) => format!("{}{path}", token_prefix(token)),
+            _ => path.to_owned(),
+        }
+    }
+
+    /* Reader preferences are applied through classes compiled from sass/includes */
+    pub fn body_class(&self) -> String {
+        format!(
+            "font-{} size-{} leading-{} width-{}",
+            self.font_family.as_str(),
+            self.font_size.as_str(),
+            self.line_height.as_str(),
+            self.content_width.as_str()
+        )
+    }
+
+    /* Accepts a bare token or any link containing one, i.e. from another instance */
+    pub fn import(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let token = match value.split_once("/s/") {
+            Some((_, rest)) => rest.split(['/', '?', '#']).next()?,
+            None => value,
+        };
+        Self::parse_token(token)
+    }
+}
+
+/* Reads operator defaults from a file with one `key = value` pair per line */
+pub fn load_defaults(path: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+
+    DEFAULTS
+        .set(parse_defaults(&content)?)
+        .map_err(|_| "defaults already loaded".to_owned())
+}
+
+fn parse_defaults(content: &str) -> Result<Settings, String> {
+    let mut settings = Settings::builtin();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected key = value", number + 1));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let Some(def) = SETTINGS.iter().find(|def| def.key == key) else {
+            return Err(format!("line {}: unknown setting {key}", number + 1));
+        };
+        if !(def.valid)(value) {
+            return Err(format!("line {}: invalid value for {key}", number + 1));
+        }
+        (def.write)(&mut settings, Some(value));
+    }
+    Ok(settings)
+}
+
+fn escape(value: String) -> String {
+    value
+        .replace('%', "%25")
+        .replace('&', "%26")
+        .replace('=', "%3D")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("%3D", "=")
+        .replace("%26", "&")
+        .replace("%25", "%")
+}
+
+/* A version character followed by base64url, anything else in /s/ isn't a token */
+pub fn is_token(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|version| version.is_ascii_alphanumeric())
+        && token.len() <= TOKEN_MAX_LEN
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/* Prefix for links carrying the settings token, i.e. "/s/<token>" */
+pub fn token_prefix(token: &str) -> String {
+    format!("/s/{token}")
+}
+
+/* Token of a request under /s/<token>/, read from the raw url so the prefix can be removed */
+pub fn url_token(request: &Request) -> Option<&str> {
+    let (token, _) = request.raw_url().strip_prefix("/s/")?.split_once('/')?;
+    is_token(token).then_some(token)
+}
+
+/* Links with ?s=<token> move the token into the path, keeping the rest of the query */
+pub fn query_token_redirect(request: &Request) -> Option<Response> {
+    let token = request.get_param("s")?;
+    if !is_token(&token) {
+        return Some(Response::text("Invalid settings token").with_status_code(400));
+    }
+
+    let raw_url = request.raw_url();
+    let (path, query) = raw_url.split_once('?').unwrap_or((raw_url, ""));
+    let query: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("s="))
+        .collect();
+
+    let mut location = format!("{}{path}", token_prefix(&token));
+    if !query.is_empty() {
+        location.push('?');
+        location.push_str(&query.join("&"));
+    }
+    Some(Response::redirect_303(location))
+}
+
+/* Local paths only, protocol relative urls and the UNPREFIXED_PATHS stay as they are */
+fn is_prefixable(path: &str) -> bool {
+    match path.strip_prefix('/') {
+        Some(rest) => {
+            !rest.starts_with(['/', '\\']) && !UNPREFIXED_PATHS.iter().any(|p| rest.starts_with(p))
+        }
+        None => false,
+    }
+}
+
+/* Redirects to local paths keep the token, pages generate their links with Settings::link */
+pub fn with_token_location(mut response: Response, token: &str) -> Response {
+    let prefix = token_prefix(token);
+    for (key, value) in response.headers.iter_mut() {
+        if key.eq_ignore_ascii_case("Location") && is_prefixable(value) {
+            *value = format!("{prefix}{value}").into();
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn form_fields() {
+        let settings = Settings::from_form(&fields(&[
+            ("embed_images", "on"),
+            ("theme", "sepia"),
+            ("font_size", "enormous"),
+            ("redirect_timer", "9000"),
+        ]));
+        assert!(settings.embed_images);
+        /* Unchecked checkboxes are missing from the form */
+        assert!(!settings.proxy_images);
+        assert!(settings.theme == Theme::Sepia);
+        assert!(settings.font_size == FontSize::Normal);
+        assert_eq!(settings.redirect_timer, 600);
+    }
+
+    #[test]
+    fn cookies() {
+        let request = Request::fake_http(
+            "GET",
+            "/",
+            vec![(
+                "Cookie".to_owned(),
+                "embed_images=true; theme=dark; unknown=1; redirect_timer=x".to_owned(),
+            )],
+            vec![],
+        );
+        let settings = Settings::from_request(&request);
+        assert!(settings.embed_images);
+        assert!(settings.proxy_images);
+        assert!(settings.theme == Theme::Dark);
+        assert_eq!(settings.redirect_timer, 5);
+    }
+
+    fn location(response: &Response) -> Option<&str> {
+        response
+            .headers
+            .iter()
+            .find(|(key, _)| key == "Location")
+            .map(|(_, value)| value.as_ref())
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let settings = Settings {
+            theme: Theme::Dark,
+            redirect_timer: 12,
+            ..Settings::builtin()
+        };
+        let token = settings.to_token();
+        assert!(is_token(&token));
+
+        let parsed = Settings::from_token(&token);
+        assert!(parsed.theme == Theme::Dark);
+        assert_eq!(parsed.redirect_timer, 12);
+        assert_eq!(parsed.url_token.as_deref(), Some(token.as_str()));
+        assert_eq!(Settings::builtin().to_token(), TOKEN_VERSION.to_string());
+    }
+
+    #[test]
+    fn tokens_are_validated() {
+        assert!(is_token("1dGg9ZGFyaw"));
+        assert!(!is_token(""));
+        assert!(!is_token("%41"));
+        assert!(!is_token("a\"onmouseover=\"alert(1)"));
+        assert!(!is_token(&"1".repeat(TOKEN_MAX_LEN + 1)));
+        assert!(Settings::parse_token("1<script>").is_none());
+    }
+
+    #[test]
+    fn url_tokens() {
+        let request = |url: &str| Request::fake_http("GET", url, vec![], vec![]);
+        assert_eq!(
+            url_token(&request("/s/1dGg9ZGFyaw/about")),
+            Some("1dGg9ZGFyaw")
+        );
+        assert_eq!(url_token(&request("/s/%41/about")), None);
+        assert_eq!(url_token(&request("/s/1abc")), None);
+        assert_eq!(url_token(&request("/about")), None);
+    }
+
+    #[test]
+    fn query_tokens_move_into_the_path() {
+        let request = |url: &str| Request::fake_http("GET", url, vec![], vec![]);
+        let response = query_token_redirect(&request("/world/?size=20&s=1abc&offset=8")).unwrap();
+        assert_eq!(location(&response), Some("/s/1abc/world/?size=20&offset=8"));
+
+        let response = query_token_redirect(&request("/about?s=1abc")).unwrap();
+        assert_eq!(location(&response), Some("/s/1abc/about"));
+
+        let response = query_token_redirect(&request("/about?s=x%0D%0ASet-Cookie:a")).unwrap();
+        assert_eq!(response.status_code, 400);
+        assert!(query_token_redirect(&request("/about")).is_none());
+    }
+
+    #[test]
+    fn links_carry_the_token() {
+        let settings = Settings::from_token("1abc");
+        assert_eq!(settings.link("/world/"), "/s/1abc/world/");
+        assert_eq!(settings.link("//evil.example/"), "//evil.example/");
+        assert_eq!(settings.link("/\\evil.example/"), "/\\evil.example/");
+        assert_eq!(
+            settings.link("/proxy/resizer/a.jpg"),
+            "/proxy/resizer/a.jpg"
+        );
+        assert_eq!(settings.link("/s/1def/"), "/s/1def/");
+        assert_eq!(
+            settings.link("https://example.com/"),
+            "https://example.com/"
+        );
+        assert_eq!(Settings::builtin().link("/world/"), "/world/");
+
+        let response = with_token_location(Response::redirect_303("/settings"), "1abc");
+        assert_eq!(location(&response), Some("/s/1abc/settings"));
+        let response = with_token_location(Response::redirect_303("//evil.example"), "1abc");
+        assert_eq!(location(&response), Some("//evil.example"));
+    }
+
+    #[test]
+    fn imports() {
+        let token = Settings {
+            theme: Theme::Sepia,
+            ..Settings::builtin()
+        }
+        .to_token();
+        for value in [
+            token.clone(),
+            format!(" {token} "),
+            format!("https://neuters.example/s/{token}/world/?offset=8"),
+            format!("/s/{token}/settings#top"),
+        ] {
+            let settings = Settings::import(&value).unwrap();
+            assert!(settings.theme == Theme::Sepia, "{value}");
+        }
+        assert!(Settings::import("https://neuters.example/world/").is_none());
+    }
+
+    #[test]
+    fn defaults_file() {
+        let settings = parse_defaults("# comment\n\nembed_images = true\ntheme=dark\n").unwrap();
+        assert!(settings.embed_images);
+        assert!(settings.theme == Theme::Dark);
+
+        assert_eq!(
+            parse_defaults("theme").err(),
+            Some("line 1: expected key = value".to_owned())
+        );
+        assert_eq!(
+            parse_defaults("\ncolour = red").err(),
+            Some("line 2: unknown setting colour".to_owned())
+        );
+        assert_eq!(
+            parse_defaults("theme = neon").err(),
+            Some("line 1: invalid value for theme".to_owned())
+        );
+    }
+
+    #[test]
+    fn tokens_follow_instance_defaults() {
+        let defaults = parse_defaults("theme = dark\nembed_images = true").unwrap();
+        let parse = |token: &str| Settings::parse_token_onto(token, defaults.clone()).unwrap();
+
+        let untouched = parse(&TOKEN_VERSION.to_string());
+        assert!(untouched.theme == Theme::Dark);
+        assert!(untouched.embed_images);
+
+        /* Going back to a builtin default still has to be recorded */
+        let changed = Settings {
+            theme: Theme::Auto,
+            font_size: FontSize::Large,
+            ..defaults.clone()
+        };
+        let parsed = parse(&changed.token_against(&defaults));
+        assert!(parsed.theme == Theme::Auto);
+        assert!(parsed.font_size == FontSize::Large);
+        assert!(parsed.embed_images);
+    }
+
+    #[test]
+    fn body_classes_have_styles() {
+        let scss = include_str!("../sass/includes/typography.scss");
+        let list = |name: &str| {
+            let start = scss.find(&format!("${name}:")).unwrap();
+            &scss[start..start + scss[start..].find(';').unwrap()]
+        };
+        for family in FontFamily::ALL {
+            assert!(list("font_families").contains(&format!("{}: (", family.as_str())));
+        }
+        for size in FontSize::ALL {
+            assert!(list("font_sizes").contains(size.as_str()));
+        }
+        for height in LineHeight::ALL {
+            assert!(list("line_heights").contains(height.as_str()));
+        }
+        for width in ContentWidth::ALL {
+            assert!(list("content_widths").contains(&format!("{}: ", width.as_str())));
+        }
+
+        let settings = Settings {
+            font_family: FontFamily::Mono,
+            font_size: FontSize::Large,
+            ..Settings::builtin()
+        };
+        assert_eq!(
+            settings.body_class(),
+            "font-mono size-20 leading-14 width-medium"
+        );
+    }
+
+    #[test]
+    fn follows() {
+        let follows = Follows::from_setting(
+            "/world/europe|/authors/jane-doe/\n/topic/x/, /world/europe/ //evil.com /\\evil.com \
+             /x;Domain=evil /",
+        )
+        .unwrap();
+        assert_eq!(
+            follows.paths(),
+            ["/world/europe/", "/authors/jane-doe/", "/topic/x/"]
+        );
+        assert!(follows.contains("/world/europe"));
+        assert_eq!(
+            Follows::from_setting(&follows.to_setting())
+                .unwrap()
+                .paths(),
+            follows.paths()
+        );
+
+        let mut follows = follows;
+        follows.unfollow("/authors/jane-doe");
+        assert_eq!(follows.paths(), ["/world/europe/", "/topic/x/"]);
+
+        let many: Vec<String> = (0..40).map(|n| format!("/topic/{n}/")).collect();
+        let follows = Follows::from_setting(&many.join("|")).unwrap();
+        assert_eq!(follows.paths(), &many[..Follows::MAX]);
+    }
+
+    #[test]
+    fn followable_paths() {
+        let sections = crate::test_util::sections();
+        for path in ["/world/", "/authors/jane-doe/", "/topic/oil/"] {
+            assert!(is_followable(&sections, path), "{path}");
+        }
+        for path in [
+            "/sports/",
+            "/authors/",
+            "/topic/oil/prices/",
+            "/world/story/",
+        ] {
+            assert!(!is_followable(&sections, path), "{path}");
+        }
+    }
+
+    #[test]
+    fn every_setting_reads_back() {
+        let settings = Settings::builtin();
+        for def in SETTINGS {
+            assert!((def.valid)(&(def.read)(&settings)), "{}", def.key);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use neuters::api::common::{Article, Articles, Pagination};
+
+use crate::{hierarchy::SectionMap, Section};
+
+pub fn section(id: &str, name: &str, parent: Option<&str>) -> Section {
+    Section {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        parent: parent.map(str::to_owned),
+        children: Vec::new(),
+    }
+}
+
+/* Home, World and Europe below it */
+pub fn sections() -> SectionMap {
+    [
+        section("/", "Home", None),
+        section("/world/", "World", Some("/")),
+        section("/world/europe/", "Europe", Some("/world/")),
+    ]
+    .into_iter()
+    .map(|section| (section.id.clone(), section))
+    .collect()
+}
+
+/* Story with just the fields listings look at */
+pub fn article(url: &str, published_time: &str) -> Article {
+    Article {
+        title: url.to_owned(),
+        subtype: None,
+        canonical_url: url.to_owned(),
+        description: String::new(),
+        content_elements: None,
+        authors: None,
+        thumbnail: None,
+        published_time: published_time.to_owned(),
+    }
+}
+
+pub fn articles(articles: Vec<Article>, total_size: Option<u32>) -> Articles {
+    Articles {
+        pagination: Pagination { total_size },
+        articles: Some(articles.into_boxed_slice()),
+        topics: None,
+    }
+}